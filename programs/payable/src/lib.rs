@@ -1,5 +1,16 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Mint, Token, TokenAccount};
+use anchor_lang::solana_program::program_option::COption;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{close_account, CloseAccount, Mint, Token, TokenAccount};
+
+pub mod schedule;
+pub mod settlement;
+
+use schedule::{add_months, BillingCycle, Schedule};
+use settlement::{
+    CancelPenalty, Dunning, Escalation, Escrow, Funding, PayoutMode, MAX_BASIS_POINTS,
+    MAX_ESCALATED_PAYMENTS, MAX_FEE_POINTS,
+};
 
 declare_id!("8rrFrtdFK3x8NBFEvaqznHg9Q9Tf2ij6bHEv2FCHotgJ");
 
@@ -19,18 +30,33 @@ pub mod payable {
 
     pub fn create_payable(
         ctx: Context<CreatePayable>,
-        amount: i64,
-        recurrent: bool,
-        number_of_recurrent_payment: i64,
-        recurrent_payment_interval: i64,
-        cancel_period: i64,
+        invoice_id: u64,
+        terms: PayableTerms,
     ) -> Result<()> {
         let counter = &mut ctx.accounts.counter;
         let payable = &mut ctx.accounts.payable;
         let clock = Clock::get()?;
 
         // check creator is not payer
-        require_keys_neq!(ctx.accounts.signer.key(), ctx.accounts.payer.key());
+        require_keys_neq!(
+            ctx.accounts.signer.key(),
+            ctx.accounts.payer.key(),
+            Error::CyclicPayable
+        );
+
+        // an invoice id can only be re-used once its previous payable has reached a final
+        // status, a live payable (created, accepted or disputed) must never be overwritten
+        let is_new_payable = payable.creator == Pubkey::default();
+        if !is_new_payable {
+            payable.expire_if_overdue(clock.unix_timestamp)?;
+        }
+        require!(
+            is_new_payable || payable.status.is_final(),
+            Error::PayableInProgress
+        );
+
+        // reject terms that can never settle correctly
+        terms.validate(clock.unix_timestamp)?;
 
         let payable_idx = counter.payable_idx_counter;
         let creator = ctx.accounts.signer.key();
@@ -38,57 +64,166 @@ pub mod payable {
         let payer = ctx.accounts.payer.key();
 
         // create payable
+        let amount = terms.amount;
         payable.payable_idx = payable_idx;
-        payable.amount = amount;
-        payable.cancel_period = clock.unix_timestamp + cancel_period;
+        payable.invoice_id = invoice_id;
+        payable.set_terms(terms, clock.unix_timestamp)?;
         payable.creator = ctx.accounts.signer.key();
         payable.payer = payer;
-        payable.recurrent = recurrent;
-        payable.number_of_recurrent_payment = number_of_recurrent_payment;
-        payable.recurrent_payment_interval = recurrent_payment_interval;
         payable.valid_payment_token = ctx.accounts.valid_token_mint.key();
-        payable.last_withdrawal = clock.unix_timestamp;
-        payable.status = 0; // locked
+        payable.start_time = 0;
+        payable.amount_released = 0;
+        payable.payments_claimed = 0;
+        payable.payments_missed = 0;
+        payable.failed_attempts = 0;
+        payable.past_due_since = 0;
+        payable.last_failed_at = 0;
+        // an invoice id re-used while its payable is still open keeps counting revisions,
+        // once it was closed the new payable index tells the payables apart
+        payable.revision = if is_new_payable {
+            0
+        } else {
+            payable
+                .revision
+                .checked_add(1)
+                .ok_or(Error::ArithmeticOverflow)?
+        };
+        payable.status = PayableStatus::Created;
 
         // update counter
-        counter.payable_idx_counter += 1;
+        counter.payable_idx_counter = counter
+            .payable_idx_counter
+            .checked_add(1)
+            .ok_or(Error::ArithmeticOverflow)?;
 
         // emit event
         emit!(PayableCreated {
             payable_idx,
+            invoice_id,
             creator,
             payer,
             valid_token,
-            amount
+            amount,
+            accept_by: payable.accept_by
+        });
+
+        Ok(())
+    }
+
+    pub fn amend_payable(ctx: Context<AmendPayable>, terms: PayableTerms) -> Result<()> {
+        let payable = &mut ctx.accounts.payable;
+        let clock = Clock::get()?;
+
+        // only creator can amend payable
+        require_keys_eq!(
+            payable.creator,
+            ctx.accounts.signer.key(),
+            Error::UnauthorizedCreator
+        );
+
+        // terms can only change before the payer accepts them, and before they expire
+        require!(
+            payable.status == PayableStatus::Created,
+            Error::InvalidStatusTransition
+        );
+        require!(
+            clock.unix_timestamp <= payable.accept_by,
+            Error::AcceptanceDeadlinePassed
+        );
+
+        // reject terms that can never settle correctly
+        terms.validate(clock.unix_timestamp)?;
+
+        // replace the terms, a payer accepting the previous revision is turned down
+        let amount = terms.amount;
+        payable.set_terms(terms, clock.unix_timestamp)?;
+        payable.revision = payable
+            .revision
+            .checked_add(1)
+            .ok_or(Error::ArithmeticOverflow)?;
+
+        // emit event
+        emit!(PayableAmended {
+            payable_idx: payable.payable_idx,
+            invoice_id: payable.invoice_id,
+            creator: payable.creator,
+            payer: payable.payer,
+            amount,
+            accept_by: payable.accept_by,
+            revision: payable.revision,
         });
 
         Ok(())
     }
 
-    pub fn accept_payable(ctx: Context<AcceptPayable>, recurrent: bool) -> Result<()> {
+    pub fn accept_payable(
+        ctx: Context<AcceptPayable>,
+        payable_idx: u64,
+        revision: u32,
+        recurrent: bool,
+        cancel_penalty: CancelPenalty,
+    ) -> Result<()> {
         let payable = &mut ctx.accounts.payable;
-        let amount = payable
-            .amount
-            .checked_mul(payable.number_of_recurrent_payment)
-            .expect("Payable::Functions::AcceptPayable::Overflow Error");
+        let clock = Clock::get()?;
+        // a pulled payable locks nothing, installments are pulled from the payer as they fall due
+        let amount = match payable.funding {
+            Funding::Escrow => payable.escrow().total().ok_or(Error::ArithmeticOverflow)?,
+            Funding::Pull => 0,
+        };
+
+        // user must accept the terms they reviewed, not a payable re-created under the same
+        // invoice id or an amended revision
+        require!(payable.payable_idx == payable_idx, Error::PayableMismatch);
+        require!(payable.revision == revision, Error::RevisionMismatch);
 
         // user must be aware of recurrent payment
-        require_eq!(payable.recurrent, recurrent);
+        require!(payable.recurrent == recurrent, Error::RecurrenceMismatch);
+
+        // user must agree to the cancel penalty
+        require!(
+            payable.cancel_penalty == cancel_penalty,
+            Error::CancelPenaltyMismatch
+        );
 
         // only valid payer can accept payable
-        require_keys_eq!(payable.payer, ctx.accounts.signer.key());
+        require_keys_eq!(
+            payable.payer,
+            ctx.accounts.signer.key(),
+            Error::UnauthorizedPayer
+        );
+
+        // payable must be accepted before its deadline
+        require!(
+            clock.unix_timestamp <= payable.accept_by,
+            Error::AcceptanceDeadlinePassed
+        );
+
+        // calendar installments due before acceptance must never be back-billed
+        if let BillingCycle::Calendar { first_due_at, .. } = payable.billing_cycle {
+            require!(
+                clock.unix_timestamp <= first_due_at,
+                Error::CalendarStartPassed
+            );
+        }
 
-        // update payable
-        payable.status = 1;
+        // update payable, the cancel window and payment schedule start on acceptance
+        payable.transition_to(PayableStatus::Accepted)?;
+        payable.cancel_deadline = clock
+            .unix_timestamp
+            .checked_add(payable.cancel_period as i64)
+            .ok_or(Error::ArithmeticOverflow)?;
+        payable.start_time = clock.unix_timestamp;
 
         // lock token to cover all payment
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.payer_ata.to_account_info(),
-            to: ctx.accounts.payable_ata.to_account_info(),
-            authority: ctx.accounts.signer.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        transfer(CpiContext::new(cpi_program, cpi_accounts), amount as u64)?;
+        if amount > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.payer_ata.to_account_info(),
+                to: ctx.accounts.payable_ata.to_account_info(),
+                authority: ctx.accounts.signer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            transfer(CpiContext::new(cpi_program, cpi_accounts), amount)?;
+        }
 
         // emit event
         emit!(PayableAccepted {
@@ -96,167 +231,148 @@ pub mod payable {
             creator: payable.creator,
             payer: payable.payer,
             valid_token: payable.valid_payment_token,
+            vault: ctx.accounts.payable_ata.key(),
             amount
         });
 
         Ok(())
     }
 
+    pub fn reject_payable(ctx: Context<RejectPayable>, reason: Option<u8>) -> Result<()> {
+        let payable = &mut ctx.accounts.payable;
+
+        // only valid payer can reject payable
+        require_keys_eq!(
+            payable.payer,
+            ctx.accounts.signer.key(),
+            Error::UnauthorizedPayer
+        );
+
+        // update payable, the account is closed to the creator once rejected
+        payable.transition_to(PayableStatus::Rejected)?;
+
+        // emit event
+        emit!(PayableRejected {
+            payable_idx: payable.payable_idx,
+            creator: payable.creator,
+            payer: payable.payer,
+            reason
+        });
+
+        Ok(())
+    }
+
     pub fn cancel_payable(ctx: Context<CancelPayable>) -> Result<()> {
         let clock = Clock::get()?;
 
         // only valid payer can cancel payable
-        require_keys_eq!(ctx.accounts.payable.payer, ctx.accounts.signer.key());
+        require_keys_eq!(
+            ctx.accounts.payable.payer,
+            ctx.accounts.signer.key(),
+            Error::UnauthorizedPayer
+        );
+
+        // only an accepted payable holds funds in escrow or pulls payments
+        require!(
+            ctx.accounts.payable.status.is_active(),
+            Error::PayableNotAccepted
+        );
 
         // get signer seed
         let bump = ctx.bumps.payable;
         let payee_seed = ctx.accounts.payee.key();
         let payer_seed = ctx.accounts.signer.key();
+        let invoice_id_seed = ctx.accounts.payable.invoice_id.to_le_bytes();
 
         let seeds = &[
             &b"payable"[..],
             payee_seed.as_ref(),
             payer_seed.as_ref(),
+            invoice_id_seed.as_ref(),
             &[bump],
         ];
         let signer_seeds = &[&seeds[..]];
 
-        // // withdraw outstanding payment
-        // get number of missed payment
-        let mut missed_payment_withdrawal = 1;
-
-        if ctx.accounts.payable.number_of_recurrent_payment > 0 {
-            let last_withdrawal_period = clock
-                .unix_timestamp
-                .checked_sub(ctx.accounts.payable.last_withdrawal)
-                .expect("Payable::Functions::CancelPayable::Overflow Error");
-            missed_payment_withdrawal = last_withdrawal_period
-                .checked_div(ctx.accounts.payable.recurrent_payment_interval)
-                .expect("Payable::Functions::CancelPayable::Overflow Error");
+        // split the escrow between payee and payer, a pulled payable is charged what fell due
+        let payable = &mut ctx.accounts.payable;
+        let escrow = payable.escrow();
+        let plan = match payable.funding {
+            Funding::Escrow => escrow.cancellation(clock.unix_timestamp),
+            Funding::Pull => {
+                let available = pull_allowance(&ctx.accounts.payer_ata, &payable.key());
+                escrow.pulled_cancellation(clock.unix_timestamp, available)
+            }
+        }
+        .ok_or(Error::ArithmeticOverflow)?;
+        payable.amount_released = plan.amount_released;
+        payable.payments_claimed = plan.payments_claimed;
+        payable.payments_missed = plan.payments_missed;
+
+        // a pulled payable is not retried anymore, what the payer could not cover is missed
+        if plan.overdue > 0 {
+            emit!(PaymentFailed {
+                payable_idx: payable.payable_idx,
+                creator: payable.creator,
+                payer: payable.payer,
+                amount_due: plan.overdue,
+                failed_attempts: payable.failed_attempts,
+            });
         }
 
-        // get amount to transfer
-        let amount_to_transfer = missed_payment_withdrawal * ctx.accounts.payable.amount as i64;
-        ctx.accounts.payable.number_of_recurrent_payment = ctx
-            .accounts
-            .payable
-            .number_of_recurrent_payment
-            .checked_sub(missed_payment_withdrawal)
-            .expect("Payable::Functions::CancelPayable::Overflow Error");
+        // update payable status
+        payable.transition_to(PayableStatus::Cancelled)?;
 
-        // transfer amount_to_transfer to payee
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.payable_ata.to_account_info(),
-            to: ctx.accounts.payee_ata.to_account_info(),
-            authority: ctx.accounts.payable.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.valid_token_mint.to_account_info();
-        transfer(
-            CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
-            amount_to_transfer as u64,
-        )?;
+        // transfer plan.to_payee to payee, from the vault or the payer as delegate
+        if plan.to_payee > 0 {
+            let from = match ctx.accounts.payable.funding {
+                Funding::Escrow => ctx.accounts.payable_ata.to_account_info(),
+                Funding::Pull => ctx.accounts.payer_ata.to_account_info(),
+            };
+            let cpi_accounts = Transfer {
+                from,
+                to: ctx.accounts.payee_ata.to_account_info(),
+                authority: ctx.accounts.payable.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            transfer(
+                CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
+                plan.to_payee,
+            )?;
+        }
 
-        // check if cancel period is over
-        let cancel_period_is_over = clock.unix_timestamp >= ctx.accounts.payable.cancel_period;
-
-        // if cancel period is over, transfer 50% of single payable payment to payee and send the rest to payer
-        if cancel_period_is_over {
-            // check if payable is recurrent
-            if ctx.accounts.payable.number_of_recurrent_payment > 0 {
-                let number_of_recurrent_payment_left =
-                    ctx.accounts.payable.number_of_recurrent_payment;
-                let temp = number_of_recurrent_payment_left
-                    .checked_mul(ctx.accounts.payable.amount)
-                    .expect("Payable::Functions::CancelPayable::Overflow Error");
-                let balance_left = temp
-                    .checked_sub(ctx.accounts.payable.amount / 2)
-                    .expect("Payable::Functions::CancelPayable::Overflow Error");
-
-                // transfer balance_left to payer
-                let cpi_accounts = Transfer {
-                    from: ctx.accounts.payable_ata.to_account_info(),
-                    to: ctx.accounts.payer_ata.to_account_info(),
-                    authority: ctx.accounts.payable.to_account_info(),
-                };
-                let cpi_program = ctx.accounts.valid_token_mint.to_account_info();
-                transfer(
-                    CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
-                    balance_left as u64,
-                )?;
+        // transfer plan.to_payer to payer
+        if plan.to_payer > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.payable_ata.to_account_info(),
+                to: ctx.accounts.payer_ata.to_account_info(),
+                authority: ctx.accounts.payable.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            transfer(
+                CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
+                plan.to_payer,
+            )?;
+        }
 
-                // transfer amount/2 to payee
-                let cpi_accounts = Transfer {
-                    from: ctx.accounts.payable_ata.to_account_info(),
-                    to: ctx.accounts.payee_ata.to_account_info(),
-                    authority: ctx.accounts.payable.to_account_info(),
-                };
-                let cpi_program = ctx.accounts.valid_token_mint.to_account_info();
-                transfer(
-                    CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
-                    ctx.accounts.payable.amount as u64 / 2,
-                )?;
-            } else {
-                // transfer amount/2 to payer
-                let cpi_accounts = Transfer {
-                    from: ctx.accounts.payable_ata.to_account_info(),
-                    to: ctx.accounts.payer_ata.to_account_info(),
-                    authority: ctx.accounts.payable.to_account_info(),
-                };
-                let cpi_program = ctx.accounts.valid_token_mint.to_account_info();
-                transfer(
-                    CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
-                    ctx.accounts.payable.amount as u64 / 2,
-                )?;
+        // emit event
+        emit!(PayableCancelled {
+            payable_idx: ctx.accounts.payable.payable_idx,
+            creator: ctx.accounts.payee.key(),
+            payer: ctx.accounts.signer.key(),
+            to_payee: plan.to_payee,
+            to_payer: plan.to_payer,
+            cancel_fee: plan.penalty,
+        });
 
-                // transfer amount/2 to payee
-                let cpi_accounts = Transfer {
-                    from: ctx.accounts.payable_ata.to_account_info(),
-                    to: ctx.accounts.payee_ata.to_account_info(),
-                    authority: ctx.accounts.payable.to_account_info(),
-                };
-                let cpi_program = ctx.accounts.valid_token_mint.to_account_info();
-                transfer(
-                    CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
-                    ctx.accounts.payable.amount as u64 / 2,
-                )?;
-            }
-        } else {
-            // update payable status
-            ctx.accounts.payable.status = 0;
-
-            // if cancel period is not over, transfer 100% to payer
-            if ctx.accounts.payable.number_of_recurrent_payment > 0 {
-                let number_of_recurrent_payment_left =
-                    ctx.accounts.payable.number_of_recurrent_payment;
-                let balance_left = number_of_recurrent_payment_left
-                    .checked_mul(ctx.accounts.payable.amount)
-                    .expect("Payable::Functions::CancelPayable::Overflow Error");
-
-                // transfer balance_left to payer
-                let cpi_accounts = Transfer {
-                    from: ctx.accounts.payable_ata.to_account_info(),
-                    to: ctx.accounts.payer_ata.to_account_info(),
-                    authority: ctx.accounts.payable.to_account_info(),
-                };
-                let cpi_program = ctx.accounts.valid_token_mint.to_account_info();
-                transfer(
-                    CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
-                    balance_left as u64,
-                )?;
-            } else {
-                // transfer amount to payer
-                let cpi_accounts = Transfer {
-                    from: ctx.accounts.payable_ata.to_account_info(),
-                    to: ctx.accounts.payer_ata.to_account_info(),
-                    authority: ctx.accounts.payable.to_account_info(),
-                };
-                let cpi_program = ctx.accounts.valid_token_mint.to_account_info();
-                transfer(
-                    CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
-                    ctx.accounts.payable.amount as u64,
-                )?;
-            }
-        }
+        // return rent once the escrow is settled
+        close_settled_payable(
+            &ctx.accounts.payable,
+            &mut ctx.accounts.payable_ata,
+            &ctx.accounts.payee,
+            &ctx.accounts.signer.to_account_info(),
+            &ctx.accounts.token_program,
+            signer_seeds,
+        )?;
 
         Ok(())
     }
@@ -265,67 +381,128 @@ pub mod payable {
         let clock = Clock::get()?;
 
         // only creator can withdraw
-        require_keys_eq!(ctx.accounts.payable.creator, ctx.accounts.signer.key());
+        require_keys_eq!(
+            ctx.accounts.payable.creator,
+            ctx.accounts.signer.key(),
+            Error::UnauthorizedCreator
+        );
 
         // withdraw can happen after cancel period
         require!(
-            clock.unix_timestamp >= ctx.accounts.payable.cancel_period,
+            clock.unix_timestamp >= ctx.accounts.payable.cancel_deadline,
             Error::WithdrawalTimeNotReached
         );
 
-        // payable must have been accepted
-        require_eq!(ctx.accounts.payable.status, 1);
-
-        // must have pending withdrawal
+        // payable must have been accepted, a past due or suspended one retries its pull
         require!(
-            ctx.accounts.payable.number_of_recurrent_payment > 0,
-            Error::CompletedPayable
+            ctx.accounts.payable.status.is_active(),
+            Error::PayableNotAccepted
         );
 
+        // must have pending withdrawal
+        let balance = ctx
+            .accounts
+            .payable
+            .escrow()
+            .balance()
+            .ok_or(Error::ArithmeticOverflow)?;
+        require!(balance > 0, Error::CompletedPayable);
+
         // get signer seed
         let bump = ctx.bumps.payable;
         let payee_seed = ctx.accounts.signer.key();
         let payer_seed = ctx.accounts.payer.key();
+        let invoice_id_seed = ctx.accounts.payable.invoice_id.to_le_bytes();
 
         let seeds = &[
             &b"payable"[..],
             payee_seed.as_ref(),
             payer_seed.as_ref(),
+            invoice_id_seed.as_ref(),
             &[bump],
         ];
         let signer_seeds = &[&seeds[..]];
 
-        // if payment is recurrent, check how many payment is left to be withdrawn
-        // transfer amount * number of payment not withdrawn
-        if ctx.accounts.payable.number_of_recurrent_payment > 1 {
-            let last_withdrawal_period = clock
-                .unix_timestamp
-                .checked_sub(ctx.accounts.payable.last_withdrawal)
-                .expect("Payable::Functions::Withdraw::Overflow Error");
-            let missed_payment_withdrawal = last_withdrawal_period
-                .checked_div(ctx.accounts.payable.recurrent_payment_interval)
-                .expect("Payable::Functions::Withdraw::Overflow Error");
-
-            let amount_to_transfer = missed_payment_withdrawal * ctx.accounts.payable.amount;
-
-            // update payment remaining
-            ctx.accounts.payable.number_of_recurrent_payment = ctx
-                .accounts
-                .payable
-                .number_of_recurrent_payment
-                .checked_sub(missed_payment_withdrawal)
-                .expect("Payable::Functions::Withdraw::Overflow Error");
-
-            // transfer amount_to_transfer to payee
+        // transfer every due payment not withdrawn
+        let payable = &mut ctx.accounts.payable;
+        let escrow = payable.escrow();
+        let plan = match payable.funding {
+            Funding::Escrow => escrow.withdrawal(clock.unix_timestamp),
+            Funding::Pull => {
+                let payer_ata = ctx
+                    .accounts
+                    .payer_ata
+                    .as_ref()
+                    .ok_or(Error::MissingPayerAta)?;
+
+                // the payer pays what they hold and have delegated to the payable
+                let available = pull_allowance(payer_ata, &payable.key());
+                escrow.pull(clock.unix_timestamp, available)
+            }
+        }
+        .ok_or(Error::ArithmeticOverflow)?;
+        let amount_to_transfer = plan.to_payee;
+
+        // must have at least one payment due, a pulled payment the payer misses still counts
+        require!(
+            amount_to_transfer > 0
+                || plan.payments_missed > escrow.payments_missed
+                || plan.overdue > 0,
+            Error::NothingDue
+        );
+
+        // update amount released and payments claimed or missed
+        payable.amount_released = plan.amount_released;
+        payable.payments_claimed = plan.payments_claimed;
+        payable.payments_missed = plan.payments_missed;
+
+        // a pull the payer could not cover is retried, a covered one brings the payable back;
+        // within the retry interval a pull only pays out what the payer did cover
+        if plan.overdue > 0 && payable.retry_due(clock.unix_timestamp)? {
+            payable.fail_payment(clock.unix_timestamp)?;
+            emit!(PaymentFailed {
+                payable_idx: payable.payable_idx,
+                creator: payable.creator,
+                payer: payable.payer,
+                amount_due: plan.overdue,
+                failed_attempts: payable.failed_attempts,
+            });
+        } else if plan.overdue > 0 {
+            require!(amount_to_transfer > 0, Error::RetryTooEarly);
+        } else if payable.status != PayableStatus::Accepted {
+            emit!(PaymentRecovered {
+                payable_idx: payable.payable_idx,
+                creator: payable.creator,
+                payer: payable.payer,
+                failed_attempts: payable.failed_attempts,
+            });
+            payable.recover_payment()?;
+        }
+
+        // update status
+        let is_completed = plan.remaining == 0;
+        if is_completed {
+            payable.transition_to(PayableStatus::Completed)?;
+        }
+        let is_cancelled = payable.status == PayableStatus::Cancelled;
+
+        // transfer amount_to_transfer to payee, from the vault or the payer as delegate
+        if amount_to_transfer > 0 {
+            let from = match &ctx.accounts.payer_ata {
+                Some(payer_ata) if ctx.accounts.payable.funding == Funding::Pull => {
+                    payer_ata.to_account_info()
+                }
+                _ => ctx.accounts.payable_ata.to_account_info(),
+            };
             let cpi_accounts = Transfer {
-                from: ctx.accounts.payable_ata.to_account_info(),
+                from,
                 to: ctx.accounts.payee_ata.to_account_info(),
                 authority: ctx.accounts.payable.to_account_info(),
             };
-            let cpi_program = ctx.accounts.valid_token_mint.to_account_info();
+            let cpi_program = ctx.accounts.token_program.to_account_info();
             transfer(
                 CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
-                amount_to_transfer as u64,
+                amount_to_transfer,
             )?;
 
             emit!(PayableWithdrawal {
@@ -335,74 +512,474 @@ pub mod payable {
                 valid_token: ctx.accounts.valid_token_mint.key(),
                 amount: amount_to_transfer
             });
-        } else {
-            // update payment remaining
-            ctx.accounts.payable.number_of_recurrent_payment =
-                ctx.accounts.payable.number_of_recurrent_payment - 1;
+        }
 
-            // update status
-            ctx.accounts.payable.status = 2;
+        // return rent once the payable is fully paid out, or cancelled after failing every retry
+        if is_completed || is_cancelled {
+            if is_completed {
+                emit!(PayableCompleted {
+                    payable_idx: ctx.accounts.payable.payable_idx,
+                    creator: ctx.accounts.payable.creator,
+                    payer: ctx.accounts.payer.key(),
+                    valid_token: ctx.accounts.payable.valid_payment_token,
+                });
+            } else {
+                emit!(PayableCancelled {
+                    payable_idx: ctx.accounts.payable.payable_idx,
+                    creator: ctx.accounts.payable.creator,
+                    payer: ctx.accounts.payer.key(),
+                    to_payee: 0,
+                    to_payer: 0,
+                    cancel_fee: 0,
+                });
+            }
 
-            // transfer amount to payee
-            let cpi_accounts = Transfer {
-                from: ctx.accounts.payable_ata.to_account_info(),
-                to: ctx.accounts.payee_ata.to_account_info(),
-                authority: ctx.accounts.payable.to_account_info(),
-            };
-            let cpi_program = ctx.accounts.valid_token_mint.to_account_info();
-            transfer(
-                CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
-                ctx.accounts.payable.amount as u64,
+            close_settled_payable(
+                &ctx.accounts.payable,
+                &mut ctx.accounts.payable_ata,
+                &ctx.accounts.signer.to_account_info(),
+                &ctx.accounts.payer,
+                &ctx.accounts.token_program,
+                signer_seeds,
             )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn approve_milestone(ctx: Context<ApproveMilestone>, milestone_idx: u8) -> Result<()> {
+        // only payer can approve a milestone
+        require_keys_eq!(
+            ctx.accounts.payable.payer,
+            ctx.accounts.signer.key(),
+            Error::UnauthorizedPayer
+        );
+
+        // payable must have been accepted
+        require!(
+            ctx.accounts.payable.status == PayableStatus::Accepted,
+            Error::PayableNotAccepted
+        );
 
+        // only milestone payables are paid on approval
+        require!(
+            ctx.accounts.payable.payout_mode == PayoutMode::Milestones,
+            Error::NotMilestonePayable
+        );
+
+        // get signer seed
+        let bump = ctx.bumps.payable;
+        let payee_seed = ctx.accounts.payee.key();
+        let payer_seed = ctx.accounts.signer.key();
+        let invoice_id_seed = ctx.accounts.payable.invoice_id.to_le_bytes();
+
+        let seeds = &[
+            &b"payable"[..],
+            payee_seed.as_ref(),
+            payer_seed.as_ref(),
+            invoice_id_seed.as_ref(),
+            &[bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        // a milestone can only be approved once
+        let payable = &mut ctx.accounts.payable;
+        let milestone = payable
+            .milestones
+            .get(milestone_idx as usize)
+            .copied()
+            .ok_or(Error::InvalidMilestone)?;
+        require!(!milestone.approved, Error::MilestoneAlreadyApproved);
+
+        // release the milestone tranche
+        let plan = payable
+            .escrow()
+            .approval(milestone.amount)
+            .ok_or(Error::ArithmeticOverflow)?;
+        payable.milestones[milestone_idx as usize].approved = true;
+        payable.amount_released = plan.amount_released;
+
+        // update status
+        let is_completed = plan.remaining == 0;
+        if is_completed {
+            payable.transition_to(PayableStatus::Completed)?;
+        }
+
+        // transfer milestone amount to payee
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.payable_ata.to_account_info(),
+            to: ctx.accounts.payee_ata.to_account_info(),
+            authority: ctx.accounts.payable.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        transfer(
+            CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
+            plan.to_payee,
+        )?;
+
+        emit!(MilestoneApproved {
+            payable_idx: ctx.accounts.payable.payable_idx,
+            creator: ctx.accounts.payable.creator,
+            payer: ctx.accounts.signer.key(),
+            milestone_idx,
+            amount: plan.to_payee,
+        });
+
+        // return rent once every milestone is paid out
+        if is_completed {
             emit!(PayableCompleted {
                 payable_idx: ctx.accounts.payable.payable_idx,
                 creator: ctx.accounts.payable.creator,
-                payer: ctx.accounts.payer.key(),
+                payer: ctx.accounts.signer.key(),
                 valid_token: ctx.accounts.payable.valid_payment_token,
             });
 
-            emit!(PayableWithdrawal {
-                payable_idx: ctx.accounts.payable.payable_idx,
-                creator: ctx.accounts.payable.creator,
-                payer: ctx.accounts.payer.key(),
-                valid_token: ctx.accounts.valid_token_mint.key(),
-                amount: ctx.accounts.payable.amount
-            });
+            close_settled_payable(
+                &ctx.accounts.payable,
+                &mut ctx.accounts.payable_ata,
+                &ctx.accounts.payee,
+                &ctx.accounts.signer.to_account_info(),
+                &ctx.accounts.token_program,
+                signer_seeds,
+            )?;
         }
 
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = signer,
-        space = 8 + Counter::LEN,
-        seeds = [b"counter"], 
-        bump
-    )]
-    pub counter: Account<'info, Counter>,
-    #[account(mut)]
-    pub signer: Signer<'info>,
-    // account holding the contract binary
-    pub system_program: Program<'info, System>,
-}
+    pub fn extend_payable(ctx: Context<ExtendPayable>, additional_payments: u64) -> Result<()> {
+        let clock = Clock::get()?;
 
-#[derive(Accounts)]
-pub struct CreatePayable<'info> {
-    #[account(mut, seeds = [b"counter"], bump)]
-    pub counter: Account<'info, Counter>,
-    #[account(
-        init_if_needed,
-        payer = signer,
-        space = 8 + Payable::LEN,
-        seeds = [
+        // only payer can extend payable
+        require_keys_eq!(
+            ctx.accounts.payable.payer,
+            ctx.accounts.signer.key(),
+            Error::UnauthorizedPayer
+        );
+
+        // payable must still be billing
+        require!(
+            ctx.accounts.payable.status.is_active(),
+            Error::PayableNotAccepted
+        );
+
+        // update payable, a vault funded payable locks the added payments too
+        let payable = &mut ctx.accounts.payable;
+        let amount = payable.extend(clock.unix_timestamp, additional_payments)?;
+        let number_of_recurrent_payment = payable.number_of_recurrent_payment;
+
+        // lock token to cover the added payments
+        if amount > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.payer_ata.to_account_info(),
+                to: ctx.accounts.payable_ata.to_account_info(),
+                authority: ctx.accounts.signer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            transfer(CpiContext::new(cpi_program, cpi_accounts), amount)?;
+        }
+
+        // emit event
+        emit!(PayableExtended {
+            payable_idx: ctx.accounts.payable.payable_idx,
+            creator: ctx.accounts.payable.creator,
+            payer: ctx.accounts.signer.key(),
+            additional_payments,
+            number_of_recurrent_payment,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn reduce_payable(ctx: Context<ReducePayable>, dropped_payments: u64) -> Result<()> {
+        let clock = Clock::get()?;
+
+        // only payer can reduce payable
+        require_keys_eq!(
+            ctx.accounts.payable.payer,
+            ctx.accounts.signer.key(),
+            Error::UnauthorizedPayer
+        );
+
+        // payable must still be billing
+        require!(
+            ctx.accounts.payable.status.is_active(),
+            Error::PayableNotAccepted
+        );
+
+        // only recurrent installments or streams can run for fewer payments
+        let payable = &ctx.accounts.payable;
+        require!(
+            payable.recurrent
+                && matches!(
+                    payable.payout_mode,
+                    PayoutMode::Installments | PayoutMode::Stream
+                )
+                && dropped_payments > 0,
+            Error::InvalidReduction
+        );
+
+        // only payments not due yet can be dropped, and at least one of them stays
+        let escrow = payable.escrow();
+        let number_of_recurrent_payment = payable
+            .number_of_recurrent_payment
+            .checked_sub(dropped_payments)
+            .ok_or(Error::InvalidReduction)?;
+        require!(
+            escrow.due_payments(clock.unix_timestamp) < number_of_recurrent_payment,
+            Error::InvalidReduction
+        );
+
+        // get signer seed
+        let bump = ctx.bumps.payable;
+        let payee_seed = ctx.accounts.payee.key();
+        let payer_seed = ctx.accounts.signer.key();
+        let invoice_id_seed = ctx.accounts.payable.invoice_id.to_le_bytes();
+
+        let seeds = &[
+            &b"payable"[..],
+            payee_seed.as_ref(),
+            payer_seed.as_ref(),
+            invoice_id_seed.as_ref(),
+            &[bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        // refund the escrow of the dropped payments, less the cancel penalty
+        let plan = escrow
+            .reduction(clock.unix_timestamp, number_of_recurrent_payment)
+            .ok_or(Error::ArithmeticOverflow)?;
+        ctx.accounts.payable.number_of_recurrent_payment = number_of_recurrent_payment;
+
+        // transfer plan.to_payee to payee
+        if plan.to_payee > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.payable_ata.to_account_info(),
+                to: ctx.accounts.payee_ata.to_account_info(),
+                authority: ctx.accounts.payable.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            transfer(
+                CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
+                plan.to_payee,
+            )?;
+        }
+
+        // transfer plan.to_payer to payer
+        if plan.to_payer > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.payable_ata.to_account_info(),
+                to: ctx.accounts.payer_ata.to_account_info(),
+                authority: ctx.accounts.payable.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            transfer(
+                CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
+                plan.to_payer,
+            )?;
+        }
+
+        // emit event
+        emit!(PayableReduced {
+            payable_idx: ctx.accounts.payable.payable_idx,
+            creator: ctx.accounts.payee.key(),
+            payer: ctx.accounts.signer.key(),
+            dropped_payments,
+            number_of_recurrent_payment,
+            to_payee: plan.to_payee,
+            to_payer: plan.to_payer,
+            cancel_fee: plan.penalty,
+        });
+
+        Ok(())
+    }
+
+    pub fn close_payable(ctx: Context<ClosePayable>) -> Result<()> {
+        let clock = Clock::get()?;
+
+        // a payable nobody accepted in time can be closed as expired
+        ctx.accounts
+            .payable
+            .expire_if_overdue(clock.unix_timestamp)?;
+
+        // only a settled payable can be closed
+        require!(
+            ctx.accounts.payable.status.is_final(),
+            Error::PayableInProgress
+        );
+
+        // get signer seed
+        let bump = ctx.bumps.payable;
+        let payee_seed = ctx.accounts.payee.key();
+        let payer_seed = ctx.accounts.payer.key();
+        let invoice_id_seed = ctx.accounts.payable.invoice_id.to_le_bytes();
+
+        let seeds = &[
+            &b"payable"[..],
+            payee_seed.as_ref(),
+            payer_seed.as_ref(),
+            invoice_id_seed.as_ref(),
+            &[bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        // a payable that was never accepted has no vault, an accepted one must sweep its vault
+        require!(
+            ctx.accounts.payable.start_time == 0 || ctx.accounts.payable_ata.is_some(),
+            Error::MissingVault
+        );
+        if let Some(payable_ata) = &ctx.accounts.payable_ata {
+            // return tokens left in the vault to the payer
+            if payable_ata.amount > 0 {
+                let payer_ata = ctx
+                    .accounts
+                    .payer_ata
+                    .as_ref()
+                    .ok_or(Error::MissingPayerAta)?;
+
+                let cpi_accounts = Transfer {
+                    from: payable_ata.to_account_info(),
+                    to: payer_ata.to_account_info(),
+                    authority: ctx.accounts.payable.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.token_program.to_account_info();
+                transfer(
+                    CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds),
+                    payable_ata.amount,
+                )?;
+            }
+
+            // vault rent goes back to the payer who opened it
+            let cpi_accounts = CloseAccount {
+                account: payable_ata.to_account_info(),
+                destination: ctx.accounts.payer.to_account_info(),
+                authority: ctx.accounts.payable.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            close_account(CpiContext::new_with_signer(
+                cpi_program,
+                cpi_accounts,
+                signer_seeds,
+            ))?;
+        }
+
+        // emit event
+        emit!(PayableClosed {
+            payable_idx: ctx.accounts.payable.payable_idx,
+            creator: ctx.accounts.payable.creator,
+            payer: ctx.accounts.payable.payer,
+        });
+
+        Ok(())
+    }
+}
+
+// the last of `count` payments must fall on a representable timestamp, and escalated
+// installments are summed one at a time so their count is capped
+fn check_schedule_length(
+    billing_cycle: BillingCycle,
+    interval: u64,
+    count: u64,
+    escalation: Escalation,
+) -> Result<()> {
+    let last_payment = match billing_cycle {
+        BillingCycle::Interval => interval
+            .checked_mul(count)
+            .filter(|length| *length <= i64::MAX as u64)
+            .map(|length| length as i64),
+        BillingCycle::Calendar {
+            period,
+            first_due_at,
+        } => count
+            .checked_sub(1)
+            .and_then(|payments| payments.checked_mul(period.months()))
+            .and_then(|months| add_months(first_due_at, months)),
+    };
+    require!(last_payment.is_some(), Error::ScheduleTooLong);
+
+    if escalation != Escalation::None {
+        require!(count <= MAX_ESCALATED_PAYMENTS, Error::InvalidEscalation);
+    }
+
+    Ok(())
+}
+
+// tokens the payer holds and has delegated to the payable, what a pull can take
+fn pull_allowance(payer_ata: &TokenAccount, payable: &Pubkey) -> u64 {
+    if payer_ata.delegate == COption::Some(*payable) && !payer_ata.is_frozen() {
+        payer_ata.amount.min(payer_ata.delegated_amount)
+    } else {
+        0
+    }
+}
+
+// closes an empty vault to the payer and the payable to its creator, a vault still
+// holding tokens is left open so close_payable can sweep it
+fn close_settled_payable<'info>(
+    payable: &Account<'info, Payable>,
+    payable_ata: &mut Account<'info, TokenAccount>,
+    creator: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    payable_ata.reload()?;
+    if payable_ata.amount > 0 {
+        return Ok(());
+    }
+
+    let cpi_accounts = CloseAccount {
+        account: payable_ata.to_account_info(),
+        destination: payer.clone(),
+        authority: payable.to_account_info(),
+    };
+    close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        cpi_accounts,
+        signer_seeds,
+    ))?;
+    payable.close(creator.clone())?;
+
+    emit!(PayableClosed {
+        payable_idx: payable.payable_idx,
+        creator: payable.creator,
+        payer: payable.payer,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + Counter::LEN,
+        seeds = [b"counter"], 
+        bump
+    )]
+    pub counter: Account<'info, Counter>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    // account holding the contract binary
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(invoice_id: u64)]
+pub struct CreatePayable<'info> {
+    #[account(mut, seeds = [b"counter"], bump)]
+    pub counter: Account<'info, Counter>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + Payable::INIT_SPACE,
+        seeds = [
             b"payable",
             signer.key().as_ref(),
-            payer.key().as_ref()
+            payer.key().as_ref(),
+            invoice_id.to_le_bytes().as_ref(),
         ],
         bump
     )]
@@ -421,14 +998,38 @@ pub struct CreatePayable<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct AmendPayable<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"payable",
+            signer.key().as_ref(),
+            payer.key().as_ref(),
+            payable.invoice_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub payable: Account<'info, Payable>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// CHECK: safe
+    pub payer: AccountInfo<'info>,
+
+    // account holding the contract binary
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct AcceptPayable<'info> {
     #[account(
         mut,
         seeds = [
-            b"payable", 
+            b"payable",
             payee.key().as_ref(),
             signer.key().as_ref(),
+            payable.invoice_id.to_le_bytes().as_ref(),
         ],
         bump
     )]
@@ -440,30 +1041,42 @@ pub struct AcceptPayable<'info> {
     #[account(mut)]
     pub payee: AccountInfo<'info>,
 
-    #[account(mut)]
+    #[account(address = payable.valid_payment_token @ Error::InvalidPaymentToken)]
     pub valid_token_mint: Account<'info, Mint>,
 
     // payer valid token ATA
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = payer_ata.owner == payable.payer @ Error::InvalidPayerAtaOwner,
+        constraint = payer_ata.mint == payable.valid_payment_token @ Error::InvalidPayerAtaMint,
+    )]
     pub payer_ata: Account<'info, TokenAccount>,
 
-    // vault valid token ATA
-    #[account(mut)]
+    // vault valid token ATA, owned by the payable PDA
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = valid_token_mint,
+        associated_token::authority = payable,
+    )]
     pub payable_ata: Account<'info, TokenAccount>,
 
     pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     // account holding the contract binary
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct CancelPayable<'info> {
+pub struct RejectPayable<'info> {
     #[account(
         mut,
+        close = payee,
         seeds = [
-            b"payable", 
+            b"payable",
             payee.key().as_ref(),
             signer.key().as_ref(),
+            payable.invoice_id.to_le_bytes().as_ref(),
         ],
         bump
     )]
@@ -475,19 +1088,55 @@ pub struct CancelPayable<'info> {
     #[account(mut)]
     pub payee: AccountInfo<'info>,
 
+    // account holding the contract binary
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPayable<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"payable",
+            payee.key().as_ref(),
+            signer.key().as_ref(),
+            payable.invoice_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub payable: Account<'info, Payable>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// CHECK: safe
     #[account(mut)]
+    pub payee: AccountInfo<'info>,
+
+    #[account(address = payable.valid_payment_token @ Error::InvalidPaymentToken)]
     pub valid_token_mint: Account<'info, Mint>,
 
-    // payer valid token ATA
-    #[account(mut)]
+    // payee valid token ATA
+    #[account(
+        mut,
+        constraint = payee_ata.owner == payable.creator @ Error::InvalidPayeeAtaOwner,
+        constraint = payee_ata.mint == payable.valid_payment_token @ Error::InvalidPayeeAtaMint,
+    )]
     pub payee_ata: Account<'info, TokenAccount>,
 
     // payer valid token ATA
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = payer_ata.owner == payable.payer @ Error::InvalidPayerAtaOwner,
+        constraint = payer_ata.mint == payable.valid_payment_token @ Error::InvalidPayerAtaMint,
+    )]
     pub payer_ata: Account<'info, TokenAccount>,
 
-    // payer valid token ATA
-    #[account(mut)]
+    // vault valid token ATA, owned by the payable PDA
+    #[account(
+        mut,
+        associated_token::mint = valid_token_mint,
+        associated_token::authority = payable,
+    )]
     pub payable_ata: Account<'info, TokenAccount>,
 
     pub token_program: Program<'info, Token>,
@@ -503,6 +1152,7 @@ pub struct WithdrawFromPayable<'info> {
             b"payable",
             signer.key().as_ref(),
             payer.key().as_ref(),
+            payable.invoice_id.to_le_bytes().as_ref(),
         ],
         bump
     )]
@@ -514,15 +1164,31 @@ pub struct WithdrawFromPayable<'info> {
     #[account(mut)]
     pub payer: AccountInfo<'info>,
 
-    #[account(mut)]
+    #[account(address = payable.valid_payment_token @ Error::InvalidPaymentToken)]
     pub valid_token_mint: Account<'info, Mint>,
 
-    // payer valid token ATA
-    #[account(mut)]
+    // payee valid token ATA
+    #[account(
+        mut,
+        constraint = payee_ata.owner == payable.creator @ Error::InvalidPayeeAtaOwner,
+        constraint = payee_ata.mint == payable.valid_payment_token @ Error::InvalidPayeeAtaMint,
+    )]
     pub payee_ata: Account<'info, TokenAccount>,
 
-    // vault valid token ATA
-    #[account(mut)]
+    // payer valid token ATA, installments of a pulled payable are taken from it
+    #[account(
+        mut,
+        constraint = payer_ata.owner == payable.payer @ Error::InvalidPayerAtaOwner,
+        constraint = payer_ata.mint == payable.valid_payment_token @ Error::InvalidPayerAtaMint,
+    )]
+    pub payer_ata: Option<Account<'info, TokenAccount>>,
+
+    // vault valid token ATA, owned by the payable PDA
+    #[account(
+        mut,
+        associated_token::mint = valid_token_mint,
+        associated_token::authority = payable,
+    )]
     pub payable_ata: Account<'info, TokenAccount>,
 
     pub token_program: Program<'info, Token>,
@@ -530,42 +1196,740 @@ pub struct WithdrawFromPayable<'info> {
     pub system_program: Program<'info, System>,
 }
 
-#[account]
-pub struct Counter {
-    pub payable_idx_counter: u64,
-}
+#[derive(Accounts)]
+pub struct ApproveMilestone<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"payable",
+            payee.key().as_ref(),
+            signer.key().as_ref(),
+            payable.invoice_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub payable: Account<'info, Payable>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
 
-#[account]
-pub struct Payable {
-    pub payable_idx: u64,
-    pub amount: i64,
-    pub cancel_period: i64,
-    pub creator: Pubkey,
-    pub payer: Pubkey,
-    pub recurrent: bool,
-    pub number_of_recurrent_payment: i64,
-    pub recurrent_payment_interval: i64,
-    pub valid_payment_token: Pubkey,
-    pub last_withdrawal: i64,
-    pub status: u8, // 0 = Created, 1 = Accepted, 2 = Completed
+    /// CHECK: safe
+    #[account(mut)]
+    pub payee: AccountInfo<'info>,
+
+    #[account(address = payable.valid_payment_token @ Error::InvalidPaymentToken)]
+    pub valid_token_mint: Account<'info, Mint>,
+
+    // payee valid token ATA
+    #[account(
+        mut,
+        constraint = payee_ata.owner == payable.creator @ Error::InvalidPayeeAtaOwner,
+        constraint = payee_ata.mint == payable.valid_payment_token @ Error::InvalidPayeeAtaMint,
+    )]
+    pub payee_ata: Account<'info, TokenAccount>,
+
+    // vault valid token ATA, owned by the payable PDA
+    #[account(
+        mut,
+        associated_token::mint = valid_token_mint,
+        associated_token::authority = payable,
+    )]
+    pub payable_ata: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    // account holding the contract binary
+    pub system_program: Program<'info, System>,
 }
 
-impl Counter {
-    pub const LEN: usize = (1 + 8);
+#[derive(Accounts)]
+pub struct ExtendPayable<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"payable",
+            payee.key().as_ref(),
+            signer.key().as_ref(),
+            payable.invoice_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub payable: Account<'info, Payable>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// CHECK: safe
+    pub payee: AccountInfo<'info>,
+
+    #[account(address = payable.valid_payment_token @ Error::InvalidPaymentToken)]
+    pub valid_token_mint: Account<'info, Mint>,
+
+    // payer valid token ATA
+    #[account(
+        mut,
+        constraint = payer_ata.owner == payable.payer @ Error::InvalidPayerAtaOwner,
+        constraint = payer_ata.mint == payable.valid_payment_token @ Error::InvalidPayerAtaMint,
+    )]
+    pub payer_ata: Account<'info, TokenAccount>,
+
+    // vault valid token ATA, owned by the payable PDA
+    #[account(
+        mut,
+        associated_token::mint = valid_token_mint,
+        associated_token::authority = payable,
+    )]
+    pub payable_ata: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    // account holding the contract binary
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReducePayable<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"payable",
+            payee.key().as_ref(),
+            signer.key().as_ref(),
+            payable.invoice_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub payable: Account<'info, Payable>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// CHECK: safe
+    pub payee: AccountInfo<'info>,
+
+    #[account(address = payable.valid_payment_token @ Error::InvalidPaymentToken)]
+    pub valid_token_mint: Account<'info, Mint>,
+
+    // payee valid token ATA
+    #[account(
+        mut,
+        constraint = payee_ata.owner == payable.creator @ Error::InvalidPayeeAtaOwner,
+        constraint = payee_ata.mint == payable.valid_payment_token @ Error::InvalidPayeeAtaMint,
+    )]
+    pub payee_ata: Account<'info, TokenAccount>,
+
+    // payer valid token ATA
+    #[account(
+        mut,
+        constraint = payer_ata.owner == payable.payer @ Error::InvalidPayerAtaOwner,
+        constraint = payer_ata.mint == payable.valid_payment_token @ Error::InvalidPayerAtaMint,
+    )]
+    pub payer_ata: Account<'info, TokenAccount>,
+
+    // vault valid token ATA, owned by the payable PDA
+    #[account(
+        mut,
+        associated_token::mint = valid_token_mint,
+        associated_token::authority = payable,
+    )]
+    pub payable_ata: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    // account holding the contract binary
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePayable<'info> {
+    #[account(
+        mut,
+        close = payee,
+        seeds = [
+            b"payable",
+            payee.key().as_ref(),
+            payer.key().as_ref(),
+            payable.invoice_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub payable: Account<'info, Payable>,
+    #[account(
+        constraint = signer.key() == payee.key() || signer.key() == payer.key()
+            @ Error::UnauthorizedSigner
+    )]
+    pub signer: Signer<'info>,
+
+    /// CHECK: safe
+    #[account(mut)]
+    pub payee: AccountInfo<'info>,
+
+    /// CHECK: safe
+    #[account(mut)]
+    pub payer: AccountInfo<'info>,
+
+    #[account(address = payable.valid_payment_token @ Error::InvalidPaymentToken)]
+    pub valid_token_mint: Account<'info, Mint>,
+
+    // payer valid token ATA, receives tokens left in the vault
+    #[account(
+        mut,
+        constraint = payer_ata.owner == payable.payer @ Error::InvalidPayerAtaOwner,
+        constraint = payer_ata.mint == payable.valid_payment_token @ Error::InvalidPayerAtaMint,
+    )]
+    pub payer_ata: Option<Account<'info, TokenAccount>>,
+
+    // vault valid token ATA, owned by the payable PDA
+    #[account(
+        mut,
+        associated_token::mint = valid_token_mint,
+        associated_token::authority = payable,
+    )]
+    pub payable_ata: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    // account holding the contract binary
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+pub struct Counter {
+    pub payable_idx_counter: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Payable {
+    pub payable_idx: u64,
+    pub invoice_id: u64,
+    pub amount: u64,
+    pub cancel_period: u64,
+    pub cancel_deadline: i64,
+    pub cancel_penalty: CancelPenalty,
+    pub accept_by: i64,
+    pub creator: Pubkey,
+    pub payer: Pubkey,
+    pub recurrent: bool,
+    pub number_of_recurrent_payment: u64,
+    pub recurrent_payment_interval: u64,
+    pub billing_cycle: BillingCycle,
+    pub escalation: Escalation,
+    pub payout_mode: PayoutMode,
+    pub funding: Funding,
+    pub dunning: Dunning,
+    #[max_len(MAX_MILESTONES)]
+    pub milestones: Vec<Milestone>,
+    #[max_len(MAX_INSTALLMENTS)]
+    pub installments: Vec<Installment>,
+    pub valid_payment_token: Pubkey,
+    pub start_time: i64,
+    pub amount_released: u64,
+    pub payments_claimed: u64,
+    pub payments_missed: u64,
+    pub failed_attempts: u8,
+    pub past_due_since: i64,
+    pub last_failed_at: i64,
+    pub revision: u32,
+    pub status: PayableStatus,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PayableTerms {
+    pub amount: u64,
+    pub recurrent: bool,
+    pub number_of_recurrent_payment: u64,
+    pub recurrent_payment_interval: u64,
+    pub billing_cycle: BillingCycle,
+    pub escalation: Escalation,
+    pub payout_mode: PayoutMode,
+    pub funding: Funding,
+    pub dunning: Dunning,
+    pub milestones: Vec<Milestone>,
+    pub installments: Vec<Installment>,
+    pub cancel_period: u64,
+    pub cancel_penalty: CancelPenalty,
+    pub acceptance_period: u64,
+}
+
+pub const MAX_MILESTONES: usize = 8;
+pub const MAX_INSTALLMENTS: usize = 12;
+
+// Tranche of a milestone payable, `description_hash` commits to the deliverable off-chain.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct Milestone {
+    pub amount: u64,
+    pub description_hash: [u8; 32],
+    pub approved: bool,
+}
+
+// Installment of a scheduled payable, due `offset` seconds after acceptance.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct Installment {
+    pub offset: u64,
+    pub amount: u64,
+}
+
+impl PayableTerms {
+    // payable terms must describe a schedule that can be fully funded and settled
+    pub fn validate(&self, now: i64) -> Result<()> {
+        require!(self.amount > 0, Error::ZeroAmount);
+        require!(
+            self.number_of_recurrent_payment > 0,
+            Error::ZeroPaymentCount
+        );
+
+        if self.recurrent && self.billing_cycle == BillingCycle::Interval {
+            require!(
+                self.recurrent_payment_interval > 0,
+                Error::ZeroPaymentInterval
+            );
+        } else if self.recurrent {
+            require!(
+                self.recurrent_payment_interval == 0,
+                Error::InvalidBillingCycle
+            );
+        } else {
+            require!(
+                self.number_of_recurrent_payment == 1,
+                Error::InvalidOneOffPaymentCount
+            );
+            require!(
+                self.recurrent_payment_interval == 0,
+                Error::InvalidOneOffPaymentInterval
+            );
+
+            // a single payment has nothing to stream over
+            require!(
+                self.payout_mode != PayoutMode::Stream,
+                Error::InvalidOneOffPayoutMode
+            );
+        }
+
+        // calendar billing pays whole installments on calendar dates
+        if let BillingCycle::Calendar { first_due_at, .. } = self.billing_cycle {
+            require!(
+                self.recurrent && self.payout_mode == PayoutMode::Installments,
+                Error::InvalidBillingCycle
+            );
+
+            // installments before the payable exists must never be billed
+            require!(first_due_at >= now, Error::InvalidBillingCycle);
+        }
+
+        // the last payment must fall on a representable timestamp, and the payment count of
+        // an escalating payable is capped before its total is computed
+        check_schedule_length(
+            self.billing_cycle,
+            self.recurrent_payment_interval,
+            self.number_of_recurrent_payment,
+            self.escalation,
+        )?;
+
+        // a grant is a one-off payable that must finish vesting on a representable
+        // timestamp, after its cliff
+        if let PayoutMode::Vesting {
+            cliff_period,
+            vesting_period,
+            granularity,
+        } = self.payout_mode
+        {
+            require!(!self.recurrent, Error::InvalidVestingSchedule);
+            require!(
+                vesting_period > 0 && vesting_period <= i64::MAX as u64,
+                Error::InvalidVestingSchedule
+            );
+            require!(
+                cliff_period <= vesting_period,
+                Error::InvalidVestingSchedule
+            );
+            require!(
+                granularity > 0 && granularity <= vesting_period,
+                Error::InvalidVestingSchedule
+            );
+        }
+
+        // escalation only raises recurrent installments
+        if self.escalation != Escalation::None {
+            require!(
+                self.recurrent && self.payout_mode == PayoutMode::Installments,
+                Error::InvalidEscalation
+            );
+        }
+        if let Escalation::BasisPoints { bps } = self.escalation {
+            require!(bps <= MAX_BASIS_POINTS, Error::InvalidEscalation);
+        }
+
+        // a pulled payable charges whole recurrent installments and holds nothing to
+        // take a cancel penalty from
+        if self.funding == Funding::Pull {
+            require!(
+                self.recurrent && self.payout_mode == PayoutMode::Installments,
+                Error::InvalidPullFunding
+            );
+            require!(
+                self.cancel_penalty == CancelPenalty::None,
+                Error::InvalidPullFunding
+            );
+        }
+
+        // only a pull can fail, so only a pulled payable retries one
+        require!(
+            self.funding == Funding::Pull || self.dunning == Dunning::default(),
+            Error::InvalidDunning
+        );
+        require!(
+            self.dunning.grace_period <= i64::MAX as u64,
+            Error::InvalidDunning
+        );
+
+        // the payer must be able to lock the whole payable at once
+        let total = self
+            .escalation
+            .total_of(self.amount, self.number_of_recurrent_payment)
+            .ok_or(Error::ArithmeticOverflow)?;
+
+        require!(
+            self.cancel_period <= i64::MAX as u64,
+            Error::InvalidCancelPeriod
+        );
+
+        // a penalty can not take more than the whole escrow
+        match self.cancel_penalty {
+            CancelPenalty::None => {}
+            CancelPenalty::BasisPoints { bps } => {
+                require!(bps <= MAX_BASIS_POINTS, Error::InvalidCancelPenalty)
+            }
+            CancelPenalty::Flat { amount } => {
+                require!(amount <= total, Error::InvalidCancelPenalty)
+            }
+            CancelPenalty::Curve { ref points, .. } => {
+                require!(
+                    !points.is_empty() && points.len() <= MAX_FEE_POINTS,
+                    Error::InvalidCancelFeeCurve
+                );
+                require!(
+                    points.iter().all(|point| point.bps <= MAX_BASIS_POINTS),
+                    Error::InvalidCancelPenalty
+                );
+                require!(
+                    points
+                        .windows(2)
+                        .all(|pair| pair[0].offset < pair[1].offset),
+                    Error::InvalidCancelFeeCurve
+                );
+            }
+        }
+
+        // milestones split a one-off payable total into tranches the payer approves
+        if self.payout_mode == PayoutMode::Milestones {
+            require!(!self.recurrent, Error::InvalidMilestones);
+            require!(
+                !self.milestones.is_empty() && self.milestones.len() <= MAX_MILESTONES,
+                Error::InvalidMilestones
+            );
+            require!(
+                self.milestones
+                    .iter()
+                    .all(|milestone| milestone.amount > 0 && !milestone.approved),
+                Error::InvalidMilestones
+            );
+
+            let milestones_total = self
+                .milestones
+                .iter()
+                .try_fold(0u64, |sum, milestone| sum.checked_add(milestone.amount))
+                .ok_or(Error::ArithmeticOverflow)?;
+            require!(milestones_total == total, Error::InvalidMilestones);
+        } else {
+            require!(self.milestones.is_empty(), Error::InvalidMilestones);
+        }
+
+        // explicit installments split a one-off payable total and are paid out in order
+        if self.payout_mode == PayoutMode::Scheduled {
+            require!(!self.recurrent, Error::InvalidInstallments);
+            require!(
+                !self.installments.is_empty() && self.installments.len() <= MAX_INSTALLMENTS,
+                Error::InvalidInstallments
+            );
+            require!(
+                self.installments
+                    .iter()
+                    .all(|installment| installment.amount > 0),
+                Error::InvalidInstallments
+            );
+            require!(
+                self.installments
+                    .windows(2)
+                    .all(|pair| pair[0].offset < pair[1].offset),
+                Error::InvalidInstallments
+            );
+            require!(
+                self.installments
+                    .last()
+                    .is_some_and(|installment| installment.offset <= i64::MAX as u64),
+                Error::ScheduleTooLong
+            );
+
+            let installments_total = self
+                .installments
+                .iter()
+                .try_fold(0u64, |sum, installment| sum.checked_add(installment.amount))
+                .ok_or(Error::ArithmeticOverflow)?;
+            require!(installments_total == total, Error::InvalidInstallments);
+        } else {
+            require!(self.installments.is_empty(), Error::InvalidInstallments);
+        }
+
+        // a payable must not wait for acceptance forever
+        require!(
+            self.acceptance_period > 0 && self.acceptance_period <= i64::MAX as u64,
+            Error::InvalidAcceptancePeriod
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+pub enum PayableStatus {
+    Created,
+    Accepted,
+    Cancelled,
+    Rejected,
+    Expired,
+    Completed,
+    Disputed,
+    PastDue,
+    Suspended,
+}
+
+impl PayableStatus {
+    // Created   -> Accepted | Rejected | Expired
+    // Accepted  -> Completed | Cancelled | Disputed | PastDue
+    // Disputed  -> Accepted | Completed | Cancelled
+    // PastDue   -> Accepted | Suspended | Cancelled
+    // Suspended -> Accepted | Cancelled
+    // Cancelled, Rejected, Expired and Completed are final
+    // Disputed is reserved for a dispute flow, no instruction sets it yet
+    pub fn can_transition_to(self, next: PayableStatus) -> bool {
+        use PayableStatus::*;
+
+        matches!(
+            (self, next),
+            (Created, Accepted)
+                | (Created, Rejected)
+                | (Created, Expired)
+                | (Accepted, Completed)
+                | (Accepted, Cancelled)
+                | (Accepted, Disputed)
+                | (Disputed, Accepted)
+                | (Disputed, Completed)
+                | (Disputed, Cancelled)
+                | (Accepted, PastDue)
+                | (PastDue, Accepted)
+                | (PastDue, Suspended)
+                | (PastDue, Cancelled)
+                | (Suspended, Accepted)
+                | (Suspended, Cancelled)
+        )
+    }
+
+    // an accepted payable keeps billing while a failed pull is retried
+    pub fn is_active(self) -> bool {
+        matches!(
+            self,
+            PayableStatus::Accepted | PayableStatus::PastDue | PayableStatus::Suspended
+        )
+    }
+
+    pub fn is_final(self) -> bool {
+        matches!(
+            self,
+            PayableStatus::Cancelled
+                | PayableStatus::Rejected
+                | PayableStatus::Expired
+                | PayableStatus::Completed
+        )
+    }
 }
 
 impl Payable {
-    pub const LEN: usize = (1 + 8)  +          // payable_idx
-        (1 + 8)  +          // amount
-        (1 + 8)  +          // critical period
-        (1 + 32)  +         // creator
-        (1 + 32) +          // payer
-        (1 + 1)  +          // recurrent
-        (1 + 8)  +          // number of recurrent payment
-        (1 + 8)  +          // recurrent payment interval
-        (1 + 32)  +         // valid token
-        (1 + 8)  +          // last withdrawal
-        (1 + 1); // status
+    // validated terms of a payable not accepted yet, its acceptance window starts at `now`
+    pub fn set_terms(&mut self, terms: PayableTerms, now: i64) -> Result<()> {
+        self.amount = terms.amount;
+        self.cancel_period = terms.cancel_period;
+        self.cancel_penalty = terms.cancel_penalty;
+        self.cancel_deadline = 0;
+        self.accept_by = now
+            .checked_add(terms.acceptance_period as i64)
+            .ok_or(Error::ArithmeticOverflow)?;
+        self.recurrent = terms.recurrent;
+        self.number_of_recurrent_payment = terms.number_of_recurrent_payment;
+        self.recurrent_payment_interval = terms.recurrent_payment_interval;
+        self.billing_cycle = terms.billing_cycle;
+        self.escalation = terms.escalation;
+        self.payout_mode = terms.payout_mode;
+        self.funding = terms.funding;
+        self.dunning = terms.dunning;
+        self.milestones = terms.milestones;
+        self.installments = terms.installments;
+
+        Ok(())
+    }
+
+    pub fn schedule(&self) -> Schedule {
+        Schedule {
+            start_time: self.start_time,
+            interval: self.recurrent_payment_interval,
+            count: self.number_of_recurrent_payment,
+            cycle: self.billing_cycle,
+        }
+    }
+
+    pub fn escrow(&self) -> Escrow {
+        Escrow {
+            amount: self.amount,
+            recurrent: self.recurrent,
+            payout_mode: self.payout_mode,
+            funding: self.funding,
+            dunning: self.dunning,
+            schedule: self.schedule(),
+            escalation: self.escalation,
+            installments: self.installments.clone(),
+            amount_released: self.amount_released,
+            payments_claimed: self.payments_claimed,
+            payments_missed: self.payments_missed,
+            cancel_deadline: self.cancel_deadline,
+            cancel_penalty: self.cancel_penalty.clone(),
+        }
+    }
+
+    // adds `additional_payments` to a recurrent schedule whose last payment is not due at
+    // `now`, returns the tokens a vault funded payable locks for them, escalated like the others
+    pub fn extend(&mut self, now: i64, additional_payments: u64) -> Result<u64> {
+        // only recurrent installments or streams can run for more payments
+        require!(
+            self.recurrent
+                && matches!(
+                    self.payout_mode,
+                    PayoutMode::Installments | PayoutMode::Stream
+                )
+                && additional_payments > 0,
+            Error::InvalidExtension
+        );
+
+        // added payments follow the last one, which must not be due yet
+        let escrow = self.escrow();
+        require!(
+            escrow.due_payments(now) < escrow.payment_count(),
+            Error::InvalidExtension
+        );
+
+        // the extended schedule must still end on a representable timestamp
+        let number_of_recurrent_payment = self
+            .number_of_recurrent_payment
+            .checked_add(additional_payments)
+            .ok_or(Error::ArithmeticOverflow)?;
+        check_schedule_length(
+            self.billing_cycle,
+            self.recurrent_payment_interval,
+            number_of_recurrent_payment,
+            self.escalation,
+        )?;
+
+        let total = escrow.total().ok_or(Error::ArithmeticOverflow)?;
+        self.number_of_recurrent_payment = number_of_recurrent_payment;
+        let amount = match self.funding {
+            Funding::Escrow => self
+                .escrow()
+                .total()
+                .and_then(|extended| extended.checked_sub(total))
+                .ok_or(Error::ArithmeticOverflow)?,
+            Funding::Pull => 0,
+        };
+
+        Ok(amount)
+    }
+
+    // a failed pull leaves the payable past due, suspended once its grace period is over and
+    // cancelled once the payer has also failed every retry; the payer gets one installment
+    // interval between failed pulls, so retries can not be spent in a single slot
+    pub fn fail_payment(&mut self, now: i64) -> Result<()> {
+        require!(self.retry_due(now)?, Error::RetryTooEarly);
+
+        self.failed_attempts = self.failed_attempts.saturating_add(1);
+        self.last_failed_at = now;
+        if self.status == PayableStatus::Accepted {
+            self.past_due_since = now;
+            self.transition_to(PayableStatus::PastDue)?;
+        }
+
+        let grace_over = self
+            .past_due_since
+            .checked_add(self.dunning.grace_period as i64)
+            .ok_or(Error::ArithmeticOverflow)?
+            <= now;
+        if grace_over && self.failed_attempts >= self.dunning.max_retries {
+            self.transition_to(PayableStatus::Cancelled)?;
+        } else if grace_over && self.status == PayableStatus::PastDue {
+            self.transition_to(PayableStatus::Suspended)?;
+        }
+
+        Ok(())
+    }
+
+    // a covered pull brings a past due or suspended payable back in good standing
+    pub fn recover_payment(&mut self) -> Result<()> {
+        self.failed_attempts = 0;
+        self.past_due_since = 0;
+        self.last_failed_at = 0;
+        self.transition_to(PayableStatus::Accepted)
+    }
+
+    // whether a failed pull at `now` counts as a retry, only one per installment interval does
+    pub fn retry_due(&self, now: i64) -> Result<bool> {
+        if self.failed_attempts == 0 {
+            return Ok(true);
+        }
+
+        let next_retry_at = self.next_retry_at().ok_or(Error::ArithmeticOverflow)?;
+        Ok(now >= next_retry_at)
+    }
+
+    // a failed pull can be retried one installment interval after the last one failed
+    pub fn next_retry_at(&self) -> Option<i64> {
+        match self.billing_cycle {
+            BillingCycle::Interval => self
+                .last_failed_at
+                .checked_add(self.recurrent_payment_interval as i64),
+            BillingCycle::Calendar { period, .. } => {
+                add_months(self.last_failed_at, period.months())
+            }
+        }
+    }
+
+    // a created payable left unaccepted past its deadline is expired
+    pub fn expire_if_overdue(&mut self, now: i64) -> Result<()> {
+        if self.status == PayableStatus::Created && now > self.accept_by {
+            self.transition_to(PayableStatus::Expired)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn transition_to(&mut self, next: PayableStatus) -> Result<()> {
+        require!(
+            self.status.can_transition_to(next),
+            Error::InvalidStatusTransition
+        );
+
+        emit!(PayableStatusChanged {
+            payable_idx: self.payable_idx,
+            from: self.status,
+            to: next,
+        });
+        self.status = next;
+
+        Ok(())
+    }
+}
+
+impl Counter {
+    pub const LEN: usize = (1 + 8);
 }
 
 #[event]
@@ -574,10 +1938,23 @@ pub struct Initialized {}
 #[event]
 pub struct PayableCreated {
     pub payable_idx: u64,
+    pub invoice_id: u64,
     pub creator: Pubkey,
     pub payer: Pubkey,
     pub valid_token: Pubkey,
-    pub amount: i64,
+    pub amount: u64,
+    pub accept_by: i64,
+}
+
+#[event]
+pub struct PayableAmended {
+    pub payable_idx: u64,
+    pub invoice_id: u64,
+    pub creator: Pubkey,
+    pub payer: Pubkey,
+    pub amount: u64,
+    pub accept_by: i64,
+    pub revision: u32,
 }
 
 #[event]
@@ -586,7 +1963,74 @@ pub struct PayableAccepted {
     pub creator: Pubkey,
     pub payer: Pubkey,
     pub valid_token: Pubkey,
-    pub amount: i64,
+    pub vault: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PayableRejected {
+    pub payable_idx: u64,
+    pub creator: Pubkey,
+    pub payer: Pubkey,
+    pub reason: Option<u8>,
+}
+
+#[event]
+pub struct PayableCancelled {
+    pub payable_idx: u64,
+    pub creator: Pubkey,
+    pub payer: Pubkey,
+    pub to_payee: u64,
+    pub to_payer: u64,
+    pub cancel_fee: u64,
+}
+
+#[event]
+pub struct PaymentFailed {
+    pub payable_idx: u64,
+    pub creator: Pubkey,
+    pub payer: Pubkey,
+    pub amount_due: u64,
+    pub failed_attempts: u8,
+}
+
+#[event]
+pub struct PaymentRecovered {
+    pub payable_idx: u64,
+    pub creator: Pubkey,
+    pub payer: Pubkey,
+    pub failed_attempts: u8,
+}
+
+#[event]
+pub struct PayableExtended {
+    pub payable_idx: u64,
+    pub creator: Pubkey,
+    pub payer: Pubkey,
+    pub additional_payments: u64,
+    pub number_of_recurrent_payment: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PayableReduced {
+    pub payable_idx: u64,
+    pub creator: Pubkey,
+    pub payer: Pubkey,
+    pub dropped_payments: u64,
+    pub number_of_recurrent_payment: u64,
+    pub to_payee: u64,
+    pub to_payer: u64,
+    pub cancel_fee: u64,
+}
+
+#[event]
+pub struct MilestoneApproved {
+    pub payable_idx: u64,
+    pub creator: Pubkey,
+    pub payer: Pubkey,
+    pub milestone_idx: u8,
+    pub amount: u64,
 }
 
 #[event]
@@ -595,7 +2039,7 @@ pub struct PayableWithdrawal {
     pub creator: Pubkey,
     pub payer: Pubkey,
     pub valid_token: Pubkey,
-    pub amount: i64,
+    pub amount: u64,
 }
 
 #[event]
@@ -606,9 +2050,316 @@ pub struct PayableCompleted {
     pub valid_token: Pubkey,
 }
 
+#[event]
+pub struct PayableClosed {
+    pub payable_idx: u64,
+    pub creator: Pubkey,
+    pub payer: Pubkey,
+}
+
+#[event]
+pub struct PayableStatusChanged {
+    pub payable_idx: u64,
+    pub from: PayableStatus,
+    pub to: PayableStatus,
+}
+
 #[error_code]
 pub enum Error {
+    #[msg("Creator and payer of a payable must be different")]
     CyclicPayable,
+    #[msg("Cancel period is not over, withdrawal is not allowed yet")]
     WithdrawalTimeNotReached,
+    #[msg("Payable has no pending payment")]
     CompletedPayable,
+    #[msg("Payable with this invoice id is still in progress")]
+    PayableInProgress,
+    #[msg("Token mint does not match the payable payment token")]
+    InvalidPaymentToken,
+    #[msg("Payer token account is not owned by the payable payer")]
+    InvalidPayerAtaOwner,
+    #[msg("Payer token account mint does not match the payable payment token")]
+    InvalidPayerAtaMint,
+    #[msg("Payee token account is not owned by the payable creator")]
+    InvalidPayeeAtaOwner,
+    #[msg("Payee token account mint does not match the payable payment token")]
+    InvalidPayeeAtaMint,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+    #[msg("Recurrent payment interval must be greater than zero")]
+    ZeroPaymentInterval,
+    #[msg("No payment is due yet")]
+    NothingDue,
+    #[msg("Payable has not been accepted")]
+    PayableNotAccepted,
+    #[msg("Recurrent flag does not match the payable")]
+    RecurrenceMismatch,
+    #[msg("Signer is not the payer of the payable")]
+    UnauthorizedPayer,
+    #[msg("Signer is not the creator of the payable")]
+    UnauthorizedCreator,
+    #[msg("Payment amount must be greater than zero")]
+    ZeroAmount,
+    #[msg("Number of payments must be greater than zero")]
+    ZeroPaymentCount,
+    #[msg("A one-off payable must have exactly one payment")]
+    InvalidOneOffPaymentCount,
+    #[msg("A one-off payable must not have a payment interval")]
+    InvalidOneOffPaymentInterval,
+    #[msg("Payment schedule does not fit in a unix timestamp")]
+    ScheduleTooLong,
+    #[msg("Cancel period does not fit in a unix timestamp")]
+    InvalidCancelPeriod,
+    #[msg("Payable status does not allow this action")]
+    InvalidStatusTransition,
+    #[msg("Signer is neither the creator nor the payer of the payable")]
+    UnauthorizedSigner,
+    #[msg("Payer token account is required to return tokens left in the vault or pull a payment")]
+    MissingPayerAta,
+    #[msg("Acceptance period must be greater than zero and fit in a unix timestamp")]
+    InvalidAcceptancePeriod,
+    #[msg("Payable was not accepted before its deadline")]
+    AcceptanceDeadlinePassed,
+    #[msg("Cancel penalty must not exceed the payable total")]
+    InvalidCancelPenalty,
+    #[msg("Cancel penalty does not match the payable")]
+    CancelPenaltyMismatch,
+    #[msg("Cancel fee curve needs at least one point, no more than the curve allows, with strictly increasing offsets")]
+    InvalidCancelFeeCurve,
+    #[msg("A one-off payable can not be streamed")]
+    InvalidOneOffPayoutMode,
+    #[msg(
+        "Vesting needs a one-off payable, a period, a cliff within it and a release step within it"
+    )]
+    InvalidVestingSchedule,
+    #[msg("Milestones must be unapproved non-zero tranches, at least one and no more than a payable holds, adding up to a one-off payable total")]
+    InvalidMilestones,
+    #[msg("Payable is not paid by milestones")]
+    NotMilestonePayable,
+    #[msg("Milestone does not exist")]
+    InvalidMilestone,
+    #[msg("Milestone has already been approved")]
+    MilestoneAlreadyApproved,
+    #[msg("Installments must be non-zero amounts in offset order, at least one and no more than a payable holds, adding up to a one-off payable total")]
+    InvalidInstallments,
+    #[msg("Escalation needs a recurrent installment payable within the escalated payment limit and a rate within the basis point limit")]
+    InvalidEscalation,
+    #[msg("Calendar billing needs a recurrent installment payable without an interval, first due no earlier than now")]
+    InvalidBillingCycle,
+    #[msg("Pulled funding needs a recurrent installment payable without a cancel penalty")]
+    InvalidPullFunding,
+    #[msg("Dunning needs pulled funding and a grace period that fits in a unix timestamp")]
+    InvalidDunning,
+    #[msg("Only a recurrent installment or stream payable not yet fully due can be extended, by at least one payment")]
+    InvalidExtension,
+    #[msg("Only payments of a recurrent installment or stream payable not yet due can be dropped, keeping at least one")]
+    InvalidReduction,
+    #[msg("Payable terms were amended since the revision the payer reviewed")]
+    RevisionMismatch,
+    #[msg("Vault token account is required to close a payable that was accepted")]
+    MissingVault,
+    #[msg("First calendar payment fell due before the payable was accepted")]
+    CalendarStartPassed,
+    #[msg("A failed payment can only be retried once per installment interval")]
+    RetryTooEarly,
+    #[msg("Payable was re-created under its invoice id since the payer reviewed it")]
+    PayableMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86_400;
+
+    fn pulled(grace_period: u64, max_retries: u8) -> Payable {
+        Payable {
+            payable_idx: 0,
+            invoice_id: 1,
+            amount: 100,
+            cancel_period: 0,
+            cancel_deadline: 0,
+            cancel_penalty: CancelPenalty::None,
+            accept_by: 0,
+            creator: Pubkey::default(),
+            payer: Pubkey::default(),
+            recurrent: true,
+            number_of_recurrent_payment: 12,
+            recurrent_payment_interval: DAY as u64,
+            billing_cycle: BillingCycle::Interval,
+            escalation: Escalation::None,
+            payout_mode: PayoutMode::Installments,
+            funding: Funding::Pull,
+            dunning: Dunning {
+                grace_period,
+                max_retries,
+            },
+            milestones: Vec::new(),
+            installments: Vec::new(),
+            valid_payment_token: Pubkey::default(),
+            start_time: 1_000,
+            amount_released: 0,
+            payments_claimed: 0,
+            payments_missed: 0,
+            failed_attempts: 0,
+            past_due_since: 0,
+            last_failed_at: 0,
+            revision: 0,
+            status: PayableStatus::Accepted,
+        }
+    }
+
+    fn escalating(step: u64, count: u64) -> Payable {
+        Payable {
+            number_of_recurrent_payment: count,
+            escalation: Escalation::Flat { step },
+            funding: Funding::Escrow,
+            ..pulled(0, 0)
+        }
+    }
+
+    #[test]
+    fn failed_pull_puts_payable_past_due() {
+        let mut payable = pulled(3 * DAY as u64, 3);
+
+        payable.fail_payment(DAY).unwrap();
+
+        assert_eq!(payable.status, PayableStatus::PastDue);
+        assert_eq!(payable.failed_attempts, 1);
+        assert_eq!(payable.past_due_since, DAY);
+        assert_eq!(payable.last_failed_at, DAY);
+    }
+
+    #[test]
+    fn failed_pull_is_retried_once_per_interval() {
+        let mut payable = pulled(3 * DAY as u64, 3);
+        payable.fail_payment(DAY).unwrap();
+
+        assert_eq!(
+            payable.fail_payment(DAY).unwrap_err(),
+            Error::RetryTooEarly.into()
+        );
+        assert_eq!(
+            payable.fail_payment(2 * DAY - 1).unwrap_err(),
+            Error::RetryTooEarly.into()
+        );
+        assert_eq!(payable.failed_attempts, 1);
+
+        payable.fail_payment(2 * DAY).unwrap();
+        assert_eq!(payable.failed_attempts, 2);
+        assert_eq!(payable.status, PayableStatus::PastDue);
+    }
+
+    #[test]
+    fn partly_covered_pull_within_retry_interval_is_not_counted() {
+        let mut payable = pulled(10 * DAY as u64, 3);
+        payable.fail_payment(1_000 + DAY + DAY / 2).unwrap();
+
+        // two installments are due, the payer covers only one of them
+        let now = 1_000 + 2 * DAY;
+        let plan = payable.escrow().pull(now, 100).unwrap();
+        assert_eq!((plan.to_payee, plan.overdue), (100, 100));
+
+        assert!(!payable.retry_due(now).unwrap());
+        assert!(payable.retry_due(1_000 + 2 * DAY + DAY / 2).unwrap());
+        assert_eq!(payable.failed_attempts, 1);
+        assert_eq!(payable.status, PayableStatus::PastDue);
+    }
+
+    #[test]
+    fn payable_is_suspended_once_grace_period_is_over() {
+        let mut payable = pulled(DAY as u64, 3);
+        payable.fail_payment(DAY).unwrap();
+
+        payable.fail_payment(2 * DAY).unwrap();
+
+        assert_eq!(payable.status, PayableStatus::Suspended);
+        assert!(payable.status.is_active());
+    }
+
+    #[test]
+    fn payable_is_cancelled_after_every_retry_failed() {
+        let mut payable = pulled(DAY as u64, 3);
+        payable.fail_payment(DAY).unwrap();
+        payable.fail_payment(2 * DAY).unwrap();
+        assert_eq!(payable.status, PayableStatus::Suspended);
+
+        payable.fail_payment(3 * DAY).unwrap();
+
+        assert_eq!(payable.failed_attempts, 3);
+        assert_eq!(payable.status, PayableStatus::Cancelled);
+    }
+
+    #[test]
+    fn retries_within_grace_period_do_not_cancel() {
+        let mut payable = pulled(10 * DAY as u64, 2);
+        payable.fail_payment(DAY).unwrap();
+        payable.fail_payment(2 * DAY).unwrap();
+        payable.fail_payment(3 * DAY).unwrap();
+
+        assert_eq!(payable.failed_attempts, 3);
+        assert_eq!(payable.status, PayableStatus::PastDue);
+
+        payable.fail_payment(11 * DAY).unwrap();
+        assert_eq!(payable.status, PayableStatus::Cancelled);
+    }
+
+    #[test]
+    fn covered_pull_recovers_suspended_payable() {
+        let mut payable = pulled(DAY as u64, 3);
+        payable.fail_payment(DAY).unwrap();
+        payable.fail_payment(2 * DAY).unwrap();
+        assert_eq!(payable.status, PayableStatus::Suspended);
+
+        payable.recover_payment().unwrap();
+
+        assert_eq!(payable.status, PayableStatus::Accepted);
+        assert_eq!(payable.failed_attempts, 0);
+        assert_eq!(payable.past_due_since, 0);
+        assert_eq!(payable.last_failed_at, 0);
+
+        // a later failure starts a fresh dunning cycle without waiting on the old one
+        payable.fail_payment(2 * DAY + 1).unwrap();
+        assert_eq!(payable.status, PayableStatus::PastDue);
+        assert_eq!(payable.failed_attempts, 1);
+    }
+
+    #[test]
+    fn extension_locks_the_escalated_added_payments() {
+        let mut payable = escalating(5, 3);
+
+        // 100, 105 and 110 are locked, the added 115 and 120 follow them
+        let amount = payable.extend(1_000, 2).unwrap();
+
+        assert_eq!(amount, 115 + 120);
+        assert_eq!(payable.number_of_recurrent_payment, 5);
+        assert_eq!(payable.escrow().total(), Some(100 + 105 + 110 + 115 + 120));
+    }
+
+    #[test]
+    fn pulled_extension_locks_nothing() {
+        let mut payable = pulled(0, 0);
+
+        assert_eq!(payable.extend(1_000, 2).unwrap(), 0);
+        assert_eq!(payable.number_of_recurrent_payment, 14);
+    }
+
+    #[test]
+    fn extension_keeps_schedule_limits() {
+        let mut payable = escalating(5, MAX_ESCALATED_PAYMENTS);
+        assert_eq!(
+            payable.extend(1_000, 1).unwrap_err(),
+            Error::InvalidEscalation.into()
+        );
+
+        let mut payable = Payable {
+            recurrent_payment_interval: i64::MAX as u64 / 12,
+            ..pulled(0, 0)
+        };
+        assert_eq!(
+            payable.extend(1_000, 1).unwrap_err(),
+            Error::ScheduleTooLong.into()
+        );
+        assert_eq!(payable.number_of_recurrent_payment, 12);
+    }
 }
@@ -0,0 +1,280 @@
+use anchor_lang::prelude::*;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+// Calendar period between two installments of a payable billed by the calendar.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum CalendarPeriod {
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl CalendarPeriod {
+    pub fn months(self) -> u64 {
+        match self {
+            CalendarPeriod::Monthly => 1,
+            CalendarPeriod::Quarterly => 3,
+            CalendarPeriod::Yearly => 12,
+        }
+    }
+}
+
+// How installments of a recurrent payable fall due: every `interval` seconds after
+// acceptance, or once per calendar period (UTC) from `first_due_at`, on the same day and
+// time, moved to the last day of the month in shorter months.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum BillingCycle {
+    Interval,
+    Calendar {
+        period: CalendarPeriod,
+        first_due_at: i64,
+    },
+}
+
+// Installment schedule of a payable, anchored to the time it was accepted.
+// Installment `k` (1-based) of a recurrent payable falls due at `start_time + k * interval`,
+// a one-off payable (zero interval) is due as soon as it starts, and a calendar payable
+// follows its billing cycle. Pure so every instruction computes due payments the same way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    pub start_time: i64,
+    pub interval: u64,
+    pub count: u64,
+    pub cycle: BillingCycle,
+}
+
+impl Schedule {
+    // number of installments that have fallen due at `now`, never more than `count`
+    pub fn due_at(&self, now: i64) -> u64 {
+        if now < self.start_time {
+            return 0;
+        }
+        if let BillingCycle::Calendar {
+            period,
+            first_due_at,
+        } = self.cycle
+        {
+            return calendar_due_at(period, first_due_at, now).min(self.count);
+        }
+        if self.interval == 0 {
+            return self.count;
+        }
+
+        let elapsed = now.abs_diff(self.start_time);
+        (elapsed / self.interval).min(self.count)
+    }
+
+    // tokens streamed by `now` at `amount` per interval, accrued every second and never
+    // more than `amount * count`
+    pub fn streamed_at(&self, now: i64, amount: u64) -> u64 {
+        if now < self.start_time {
+            return 0;
+        }
+        if self.interval == 0 {
+            return amount.saturating_mul(self.count);
+        }
+
+        let elapsed = now.abs_diff(self.start_time) as u128;
+        let duration = self.interval as u128 * self.count as u128;
+        (amount as u128 * elapsed.min(duration) / self.interval as u128) as u64
+    }
+}
+
+// Vesting of a grant from `start_time`: nothing vests before the cliff, then the grant vests
+// linearly until `vesting_period` has passed, released in steps of `granularity` seconds.
+// Vested amounts round down in favour of the payer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Vesting {
+    pub start_time: i64,
+    pub cliff_period: u64,
+    pub vesting_period: u64,
+    pub granularity: u64,
+}
+
+impl Vesting {
+    // tokens of `total` vested by `now`
+    pub fn vested_at(&self, now: i64, total: u64) -> u64 {
+        if now < self.start_time {
+            return 0;
+        }
+
+        let elapsed = now.abs_diff(self.start_time);
+        if elapsed < self.cliff_period {
+            return 0;
+        }
+        if elapsed >= self.vesting_period || self.granularity == 0 {
+            return total;
+        }
+
+        let released = elapsed - elapsed % self.granularity;
+        (total as u128 * released as u128 / self.vesting_period as u128) as u64
+    }
+}
+
+// number of calendar installments from `first_due_at` that have fallen due at `now`
+fn calendar_due_at(period: CalendarPeriod, first_due_at: i64, now: i64) -> u64 {
+    if now < first_due_at {
+        return 0;
+    }
+
+    // the installment due in the month of `now` may still be ahead of it
+    let months = period.months();
+    let mut elapsed = months_between(first_due_at, now) / months;
+    let due = elapsed
+        .checked_mul(months)
+        .and_then(|months| add_months(first_due_at, months));
+    if !matches!(due, Some(due) if due <= now) {
+        elapsed -= 1;
+    }
+
+    elapsed.saturating_add(1)
+}
+
+// due date `months` calendar months after `timestamp`, None once it overflows
+pub fn add_months(timestamp: i64, months: u64) -> Option<i64> {
+    let days = timestamp.div_euclid(SECONDS_PER_DAY);
+    let seconds = timestamp.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+
+    if months > i64::MAX as u64 {
+        return None;
+    }
+    let month_index = year
+        .checked_mul(12)?
+        .checked_add(month as i64 - 1)?
+        .checked_add(months as i64)?;
+    let year = month_index.div_euclid(12);
+    let month = month_index.rem_euclid(12) as u32 + 1;
+    let day = day.min(days_in_month(year, month));
+
+    days_from_civil(year, month, day)?
+        .checked_mul(SECONDS_PER_DAY)?
+        .checked_add(seconds)
+}
+
+// whole calendar months from the month of `from` to the month of `to`
+fn months_between(from: i64, to: i64) -> u64 {
+    let (from_year, from_month, _) = civil_from_days(from.div_euclid(SECONDS_PER_DAY));
+    let (to_year, to_month, _) = civil_from_days(to.div_euclid(SECONDS_PER_DAY));
+
+    let months = (to_year - from_year) * 12 + to_month as i64 - from_month as i64;
+    months.max(0) as u64
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: u32, day: u32) -> Option<i64> {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era.checked_mul(146_097)?
+        .checked_add(day_of_era)?
+        .checked_sub(719_468)
+}
+
+// proleptic Gregorian date of a number of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // 2024-01-31 09:00:00 UTC
+    const JAN_31_2024: i64 = 1_706_691_600;
+
+    fn monthly_from(first_due_at: i64, count: u64) -> Schedule {
+        Schedule {
+            start_time: first_due_at - 1_000,
+            interval: 0,
+            count,
+            cycle: BillingCycle::Calendar {
+                period: CalendarPeriod::Monthly,
+                first_due_at,
+            },
+        }
+    }
+
+    #[test]
+    fn add_months_clamps_to_month_end() {
+        // 2024-02-29 09:00:00 UTC, a leap day
+        assert_eq!(add_months(JAN_31_2024, 1), Some(1_709_197_200));
+        // 2024-04-30 09:00:00 UTC
+        assert_eq!(add_months(JAN_31_2024, 3), Some(1_714_467_600));
+        // 2025-01-31 09:00:00 UTC
+        assert_eq!(add_months(JAN_31_2024, 12), Some(1_738_314_000));
+        assert_eq!(add_months(i64::MAX, 1), None);
+    }
+
+    #[test]
+    fn calendar_installments_fall_due_on_their_day() {
+        let schedule = monthly_from(JAN_31_2024, 12);
+
+        assert_eq!(schedule.due_at(JAN_31_2024 - 1), 0);
+        assert_eq!(schedule.due_at(JAN_31_2024), 1);
+        assert_eq!(schedule.due_at(1_709_197_199), 1);
+        assert_eq!(schedule.due_at(1_709_197_200), 2);
+        // 2024-03-30 09:00:00 UTC, the march installment falls on the 31st
+        assert_eq!(schedule.due_at(1_711_789_200), 2);
+        assert_eq!(schedule.due_at(i64::MAX), 12);
+    }
+
+    #[test]
+    fn dates_round_trip_through_days() {
+        for days in [-719_468, -1, 0, 19_753, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), Some(days));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn calendar_installments_fall_due_in_order(
+            first_due_at in 0..=4_102_444_800i64,
+            period in prop_oneof![
+                Just(CalendarPeriod::Monthly),
+                Just(CalendarPeriod::Quarterly),
+                Just(CalendarPeriod::Yearly),
+            ],
+            installment in 0..120u64,
+        ) {
+            let schedule = Schedule {
+                cycle: BillingCycle::Calendar { period, first_due_at },
+                ..monthly_from(first_due_at, 120)
+            };
+            let due_at = add_months(first_due_at, installment * period.months()).unwrap();
+
+            prop_assert_eq!(schedule.due_at(due_at), installment + 1);
+            prop_assert_eq!(schedule.due_at(due_at - 1), installment);
+        }
+    }
+}
@@ -0,0 +1,1127 @@
+use anchor_lang::prelude::*;
+
+use crate::schedule::{Schedule, Vesting};
+use crate::Installment;
+
+pub const MAX_BASIS_POINTS: u16 = 10_000;
+pub const MAX_FEE_POINTS: usize = 4;
+pub const MAX_ESCALATED_PAYMENTS: u64 = 120;
+
+// Share of the escrow left for undue payments that the payee keeps when the payer cancels
+// after the cancel period. Basis points round down in favour of the payer and a flat
+// penalty never takes more than is left in escrow.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq, InitSpace)]
+pub enum CancelPenalty {
+    None,
+    BasisPoints {
+        bps: u16,
+    },
+    Flat {
+        amount: u64,
+    },
+    Curve {
+        interpolation: FeeInterpolation,
+        #[max_len(MAX_FEE_POINTS)]
+        points: Vec<FeePoint>,
+    },
+}
+
+// Cancel fee of `bps` once a payable has been accepted for `offset` seconds.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct FeePoint {
+    pub offset: u64,
+    pub bps: u16,
+}
+
+// How a fee curve moves between its points: `Step` keeps the fee of the last point reached,
+// `Linear` ramps towards the next one. Either way the fee is flat before the first and after
+// the last point.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum FeeInterpolation {
+    Step,
+    Linear,
+}
+
+impl CancelPenalty {
+    // penalty on `balance` for a payable cancelled `elapsed` seconds after acceptance
+    pub fn applied_to(&self, balance: u64, elapsed: u64) -> u64 {
+        match self {
+            CancelPenalty::None => 0,
+            CancelPenalty::BasisPoints { bps } => basis_points_of(balance, *bps),
+            CancelPenalty::Flat { amount } => (*amount).min(balance),
+            CancelPenalty::Curve {
+                interpolation,
+                points,
+            } => basis_points_of(balance, interpolation.bps_at(points, elapsed)),
+        }
+    }
+}
+
+impl FeeInterpolation {
+    // fee in basis points `elapsed` seconds after acceptance, points must have strictly
+    // increasing offsets, linear ramps round down
+    pub fn bps_at(self, points: &[FeePoint], elapsed: u64) -> u16 {
+        let first = match points.first() {
+            Some(first) => first,
+            None => return 0,
+        };
+        if elapsed <= first.offset {
+            return first.bps;
+        }
+
+        for pair in points.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if elapsed >= to.offset {
+                continue;
+            }
+
+            return match self {
+                FeeInterpolation::Step => from.bps,
+                FeeInterpolation::Linear => {
+                    let span = (to.offset - from.offset) as u128;
+                    let progress = (elapsed - from.offset) as u128;
+                    let bps =
+                        (from.bps as u128 * (span - progress) + to.bps as u128 * progress) / span;
+                    bps as u16
+                }
+            };
+        }
+
+        points[points.len() - 1].bps
+    }
+}
+
+// Increase of every recurrent installment over the previous one: a flat `step` of tokens
+// or `bps` of the previous installment, rounded down. Escalated totals are summed one
+// installment at a time, so escalating payables are capped at `MAX_ESCALATED_PAYMENTS`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum Escalation {
+    None,
+    Flat { step: u64 },
+    BasisPoints { bps: u16 },
+}
+
+impl Escalation {
+    // installment following `installment`
+    pub fn next(self, installment: u64) -> Option<u64> {
+        match self {
+            Escalation::None => Some(installment),
+            Escalation::Flat { step } => installment.checked_add(step),
+            Escalation::BasisPoints { bps } => {
+                installment.checked_add(basis_points_of(installment, bps))
+            }
+        }
+    }
+
+    // installment `payment` (0-based), the first one being `amount`
+    pub fn nth(self, amount: u64, payment: u64) -> Option<u64> {
+        if self == Escalation::None {
+            return Some(amount);
+        }
+
+        (0..payment).try_fold(amount, |installment, _| self.next(installment))
+    }
+
+    // tokens owed for the first `payments` installments, the first one being `amount`
+    pub fn total_of(self, amount: u64, payments: u64) -> Option<u64> {
+        if self == Escalation::None {
+            return amount.checked_mul(payments);
+        }
+
+        let mut installment = amount;
+        let mut total: u64 = 0;
+        for payment in 0..payments {
+            if payment > 0 {
+                installment = self.next(installment)?;
+            }
+            total = total.checked_add(installment)?;
+        }
+
+        Some(total)
+    }
+}
+
+fn basis_points_of(balance: u64, bps: u16) -> u64 {
+    (balance as u128 * bps.min(MAX_BASIS_POINTS) as u128 / MAX_BASIS_POINTS as u128) as u64
+}
+
+// How the escrow of a payable accrues to the payee: in whole installments of `amount`
+// every interval, streamed every second at `amount` per interval, vested as a whole
+// after a cliff, per milestone once the payer approves it, or following an explicit list
+// of installments. Vesting periods and installment offsets are seconds after acceptance.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum PayoutMode {
+    Installments,
+    Stream,
+    Vesting {
+        cliff_period: u64,
+        vesting_period: u64,
+        granularity: u64,
+    },
+    Milestones,
+    Scheduled,
+}
+
+// Where the tokens of a payable come from: locked in the vault on acceptance, or pulled
+// from the payer token account as each installment falls due, through a delegate approval
+// the payer gives the payable PDA.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum Funding {
+    Escrow,
+    Pull,
+}
+
+// How long a pulled payable stays past due after a failed pull before it is suspended, and
+// how many failed pulls it takes to cancel it once that grace period is over. Without retries
+// an installment the payer can not cover is recorded as missed straight away.
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, InitSpace,
+)]
+pub struct Dunning {
+    pub grace_period: u64,
+    pub max_retries: u8,
+}
+
+impl Dunning {
+    pub fn retries(self) -> bool {
+        self.max_retries > 0
+    }
+}
+
+// Tokens an accepted payable holds in escrow and the terms deciding who they are paid to.
+// Pure so withdrawal and cancellation split the vault the same way everywhere.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Escrow {
+    pub amount: u64,
+    pub recurrent: bool,
+    pub payout_mode: PayoutMode,
+    pub funding: Funding,
+    pub dunning: Dunning,
+    pub schedule: Schedule,
+    pub escalation: Escalation,
+    pub installments: Vec<Installment>,
+    pub amount_released: u64,
+    pub payments_claimed: u64,
+    pub payments_missed: u64,
+    pub cancel_deadline: i64,
+    pub cancel_penalty: CancelPenalty,
+}
+
+// What leaves the vault (or the payer token account of a pulled payable) for each party,
+// what is still owed and how many tokens and installments are paid out or missed once the
+// settlement is done. `penalty` is the part of `to_payee` kept as cancel penalty and
+// `overdue` the tokens due that a pull could not take, retried later unless the payable is
+// being cancelled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SettlementPlan {
+    pub to_payee: u64,
+    pub to_payer: u64,
+    pub remaining: u64,
+    pub amount_released: u64,
+    pub payments_claimed: u64,
+    pub payments_missed: u64,
+    pub penalty: u64,
+    pub overdue: u64,
+}
+
+impl Escrow {
+    // tokens locked for the whole payable
+    pub fn total(&self) -> Option<u64> {
+        self.escalation.total_of(self.amount, self.schedule.count)
+    }
+
+    // tokens held for payments not paid out yet
+    pub fn balance(&self) -> Option<u64> {
+        self.total()?.checked_sub(self.amount_released)
+    }
+
+    // number of installments the payable is paid in
+    pub fn payment_count(&self) -> u64 {
+        match self.payout_mode {
+            PayoutMode::Scheduled => self.installments.len() as u64,
+            _ => self.schedule.count,
+        }
+    }
+
+    // number of installments that have fallen due at `now`
+    pub fn due_payments(&self, now: i64) -> u64 {
+        match self.payout_mode {
+            PayoutMode::Scheduled if now >= self.schedule.start_time => {
+                let elapsed = now.abs_diff(self.schedule.start_time);
+                self.installments
+                    .iter()
+                    .take_while(|installment| installment.offset <= elapsed)
+                    .count() as u64
+            }
+            PayoutMode::Scheduled => 0,
+            _ => self.schedule.due_at(now),
+        }
+    }
+
+    // tokens accrued to the payee by `now`, paid out or not
+    pub fn accrued_at(&self, now: i64) -> Option<u64> {
+        match self.payout_mode {
+            PayoutMode::Installments => self
+                .escalation
+                .total_of(self.amount, self.schedule.due_at(now)),
+            PayoutMode::Stream => Some(self.schedule.streamed_at(now, self.amount)),
+            PayoutMode::Vesting {
+                cliff_period,
+                vesting_period,
+                granularity,
+            } => {
+                let vesting = Vesting {
+                    start_time: self.schedule.start_time,
+                    cliff_period,
+                    vesting_period,
+                    granularity,
+                };
+                Some(vesting.vested_at(now, self.total()?))
+            }
+            // milestones accrue only when approved, and are paid out on approval
+            PayoutMode::Milestones => Some(self.amount_released),
+            // explicit installments are paid out in order
+            PayoutMode::Scheduled => self
+                .installments
+                .iter()
+                .take(self.due_payments(now) as usize)
+                .try_fold(0u64, |sum, installment| sum.checked_add(installment.amount)),
+        }
+    }
+
+    // pays the payee everything accrued by `now`
+    pub fn withdrawal(&self, now: i64) -> Option<SettlementPlan> {
+        let to_payee = self.accrued_at(now)?.saturating_sub(self.amount_released);
+
+        Some(SettlementPlan {
+            to_payee,
+            to_payer: 0,
+            remaining: self.balance()?.checked_sub(to_payee)?,
+            amount_released: self.amount_released.checked_add(to_payee)?,
+            payments_claimed: self.due_payments(now).max(self.payments_claimed),
+            payments_missed: self.payments_missed,
+            penalty: 0,
+            overdue: 0,
+        })
+    }
+
+    // pays the payee a tranche of `amount` the payer approved
+    pub fn approval(&self, amount: u64) -> Option<SettlementPlan> {
+        Some(SettlementPlan {
+            to_payee: amount,
+            to_payer: 0,
+            remaining: self.balance()?.checked_sub(amount)?,
+            amount_released: self.amount_released.checked_add(amount)?,
+            payments_claimed: self.payments_claimed,
+            payments_missed: self.payments_missed,
+            penalty: 0,
+            overdue: 0,
+        })
+    }
+
+    // pulls the installments due at `now` and not handled yet from the payer, in order, while
+    // `available` (what the payer holds and has delegated to the payable) covers them; every
+    // installment it can not cover is left overdue for a retry, or recorded as missed when
+    // the payable has no retries
+    pub fn pull(&self, now: i64, available: u64) -> Option<SettlementPlan> {
+        let handled = self.payments_claimed.checked_add(self.payments_missed)?;
+        let due = self.due_payments(now).saturating_sub(handled);
+
+        let (pulled, to_payee) = if self.escalation == Escalation::None {
+            let pulled = due.min(available.checked_div(self.amount)?);
+            (pulled, pulled.checked_mul(self.amount)?)
+        } else {
+            // escalated installments only grow, so they are pulled until one is not covered
+            let mut installment = self.escalation.nth(self.amount, handled)?;
+            let mut pulled = 0;
+            let mut to_payee: u64 = 0;
+            while pulled < due && to_payee.checked_add(installment)? <= available {
+                to_payee += installment;
+                pulled += 1;
+                if pulled < due {
+                    installment = self.escalation.next(installment)?;
+                }
+            }
+            (pulled, to_payee)
+        };
+
+        let owed = self
+            .escalation
+            .total_of(self.amount, handled.checked_add(due)?)?;
+        let (missed, overdue) = if self.dunning.retries() {
+            let paid = self.escalation.total_of(self.amount, handled)?;
+            (0, owed.checked_sub(paid)?.checked_sub(to_payee)?)
+        } else {
+            (due - pulled, 0)
+        };
+
+        let handled = handled.checked_add(pulled)?.checked_add(missed)?;
+        Some(SettlementPlan {
+            to_payee,
+            to_payer: 0,
+            remaining: self
+                .total()?
+                .checked_sub(self.escalation.total_of(self.amount, handled)?)?,
+            amount_released: self.amount_released.checked_add(to_payee)?,
+            payments_claimed: self.payments_claimed.checked_add(pulled)?,
+            payments_missed: self.payments_missed.checked_add(missed)?,
+            penalty: 0,
+            overdue,
+        })
+    }
+
+    // a pulled payable holds nothing to split, cancelling it at `now` makes a last pull of the
+    // installments due and not handled yet, and records every one `available` can not cover
+    // as missed since there is no later withdrawal to retry it
+    pub fn pulled_cancellation(&self, now: i64, available: u64) -> Option<SettlementPlan> {
+        let last_pull = Escrow {
+            dunning: Dunning::default(),
+            ..self.clone()
+        };
+        let plan = last_pull.pull(now, available)?;
+
+        let handled = self.payments_claimed.checked_add(self.payments_missed)?;
+        let owed = self
+            .escalation
+            .total_of(
+                self.amount,
+                plan.payments_claimed.checked_add(plan.payments_missed)?,
+            )?
+            .checked_sub(self.escalation.total_of(self.amount, handled)?)?;
+
+        Some(SettlementPlan {
+            remaining: 0,
+            overdue: owed.checked_sub(plan.to_payee)?,
+            ..plan
+        })
+    }
+
+    // shortens a recurrent payable to `count` payments, the escrow locked for the dropped ones
+    // goes back to the payer, less the cancel penalty once the cancel period is over
+    pub fn reduction(&self, now: i64, count: u64) -> Option<SettlementPlan> {
+        let total = self.escalation.total_of(self.amount, count)?;
+        let dropped = match self.funding {
+            Funding::Escrow => self.total()?.checked_sub(total)?,
+            Funding::Pull => 0,
+        };
+
+        let mut penalty = 0;
+        if now >= self.cancel_deadline {
+            let elapsed = now.saturating_sub(self.schedule.start_time).max(0) as u64;
+            penalty = self.cancel_penalty.applied_to(dropped, elapsed);
+        }
+
+        Some(SettlementPlan {
+            to_payee: penalty,
+            to_payer: dropped - penalty,
+            remaining: total.checked_sub(self.amount_released)?,
+            amount_released: self.amount_released,
+            payments_claimed: self.payments_claimed,
+            payments_missed: self.payments_missed,
+            penalty,
+            overdue: 0,
+        })
+    }
+
+    // pays the payee what accrued by `now` and returns the rest of the escrow to the payer,
+    // once the cancel period is over the payee also keeps the cancel penalty
+    pub fn cancellation(&self, now: i64) -> Option<SettlementPlan> {
+        // a one-off payment is never due at cancellation, it is settled by the cancel rules,
+        // while a vested grant is kept by the payee
+        let pays_accrued = self.recurrent || self.payout_mode != PayoutMode::Installments;
+        let due = if pays_accrued {
+            self.withdrawal(now)?
+        } else {
+            SettlementPlan {
+                amount_released: self.amount_released,
+                payments_claimed: self.payments_claimed,
+                payments_missed: self.payments_missed,
+                ..SettlementPlan::default()
+            }
+        };
+        let mut to_payee = due.to_payee;
+        let mut to_payer = self.balance()?.checked_sub(to_payee)?;
+
+        let mut penalty = 0;
+        if now >= self.cancel_deadline {
+            let elapsed = now.saturating_sub(self.schedule.start_time).max(0) as u64;
+            penalty = self.cancel_penalty.applied_to(to_payer, elapsed);
+            to_payee = to_payee.checked_add(penalty)?;
+            to_payer -= penalty;
+        }
+
+        Some(SettlementPlan {
+            to_payee,
+            to_payer,
+            remaining: 0,
+            amount_released: due.amount_released,
+            payments_claimed: due.payments_claimed,
+            payments_missed: due.payments_missed,
+            penalty,
+            overdue: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::BillingCycle;
+    use proptest::prelude::*;
+
+    fn escrow(amount: u64, recurrent: bool, interval: u64, count: u64) -> Escrow {
+        Escrow {
+            amount,
+            recurrent,
+            payout_mode: PayoutMode::Installments,
+            funding: Funding::Escrow,
+            dunning: Dunning::default(),
+            schedule: Schedule {
+                start_time: 1_000,
+                interval,
+                count,
+                cycle: BillingCycle::Interval,
+            },
+            escalation: Escalation::None,
+            installments: Vec::new(),
+            amount_released: 0,
+            payments_claimed: 0,
+            payments_missed: 0,
+            cancel_deadline: 1_100,
+            cancel_penalty: CancelPenalty::BasisPoints { bps: 5_000 },
+        }
+    }
+
+    fn any_fee_points() -> impl Strategy<Value = Vec<FeePoint>> {
+        proptest::collection::vec((1..=1_000_000u64, 0..=MAX_BASIS_POINTS), 1..=MAX_FEE_POINTS)
+            .prop_map(|steps| {
+                let mut offset = 0;
+                steps
+                    .into_iter()
+                    .map(|(step, bps)| {
+                        offset += step;
+                        FeePoint { offset, bps }
+                    })
+                    .collect()
+            })
+    }
+
+    fn any_cancel_penalty() -> impl Strategy<Value = CancelPenalty> {
+        prop_oneof![
+            Just(CancelPenalty::None),
+            (0..=MAX_BASIS_POINTS).prop_map(|bps| CancelPenalty::BasisPoints { bps }),
+            any::<u64>().prop_map(|amount| CancelPenalty::Flat { amount }),
+            (
+                prop_oneof![Just(FeeInterpolation::Step), Just(FeeInterpolation::Linear)],
+                any_fee_points()
+            )
+                .prop_map(|(interpolation, points)| CancelPenalty::Curve {
+                    interpolation,
+                    points
+                }),
+        ]
+    }
+
+    fn decaying_curve() -> Vec<FeePoint> {
+        vec![
+            FeePoint {
+                offset: 100,
+                bps: 10_000,
+            },
+            FeePoint {
+                offset: 200,
+                bps: 5_000,
+            },
+            FeePoint {
+                offset: 400,
+                bps: 0,
+            },
+        ]
+    }
+
+    fn any_installments() -> impl Strategy<Value = Vec<Installment>> {
+        proptest::collection::vec((1..=1_000_000u64, 1..=u32::MAX as u64), 1..=12).prop_map(
+            |steps| {
+                let mut offset = 0;
+                steps
+                    .into_iter()
+                    .map(|(step, amount)| {
+                        offset += step;
+                        Installment { offset, amount }
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    fn any_escrow() -> impl Strategy<Value = Escrow> {
+        (
+            1..=u32::MAX as u64,
+            any::<bool>(),
+            1..=1_000_000u64,
+            1..=1_000u64,
+            0..=1_000_000_000i64,
+            0..=1_000_000i64,
+            any_cancel_penalty(),
+            prop_oneof![
+                Just(PayoutMode::Installments),
+                Just(PayoutMode::Stream),
+                (0..=1_000u64, 1..=1_000u64, 1..=1_000u64).prop_map(
+                    |(cliff_period, vesting, granularity)| PayoutMode::Vesting {
+                        cliff_period,
+                        vesting_period: cliff_period + vesting,
+                        granularity,
+                    }
+                ),
+                Just(PayoutMode::Scheduled),
+            ],
+            any_installments(),
+            prop_oneof![
+                Just(Escalation::None),
+                (0..=u32::MAX as u64).prop_map(|step| Escalation::Flat { step }),
+                (0..=500u16).prop_map(|bps| Escalation::BasisPoints { bps }),
+            ],
+        )
+            .prop_map(
+                |(
+                    amount,
+                    recurrent,
+                    interval,
+                    count,
+                    start_time,
+                    window,
+                    cancel_penalty,
+                    payout_mode,
+                    installments,
+                    escalation,
+                )| {
+                    let (interval, count, payout_mode) = match (recurrent, payout_mode) {
+                        (true, PayoutMode::Scheduled) => {
+                            (interval, count, PayoutMode::Installments)
+                        }
+                        (true, _) => (interval, count, payout_mode),
+                        (false, PayoutMode::Stream) => (0, 1, PayoutMode::Installments),
+                        (false, _) => (0, 1, payout_mode),
+                    };
+                    let (count, escalation) =
+                        if recurrent && payout_mode == PayoutMode::Installments {
+                            (count.min(MAX_ESCALATED_PAYMENTS), escalation)
+                        } else {
+                            (count, Escalation::None)
+                        };
+                    let (amount, installments) = if payout_mode == PayoutMode::Scheduled {
+                        (
+                            installments
+                                .iter()
+                                .map(|installment| installment.amount)
+                                .sum(),
+                            installments,
+                        )
+                    } else {
+                        (amount, Vec::new())
+                    };
+                    Escrow {
+                        amount,
+                        recurrent,
+                        payout_mode,
+                        funding: Funding::Escrow,
+                        dunning: Dunning::default(),
+                        schedule: Schedule {
+                            start_time,
+                            interval,
+                            count,
+                            cycle: BillingCycle::Interval,
+                        },
+                        escalation,
+                        installments,
+                        amount_released: 0,
+                        payments_claimed: 0,
+                        payments_missed: 0,
+                        cancel_deadline: start_time + window,
+                        cancel_penalty,
+                    }
+                },
+            )
+    }
+
+    #[test]
+    fn withdrawal_pays_due_installments() {
+        let escrow = escrow(10, true, 100, 5);
+
+        let plan = escrow.withdrawal(1_250).unwrap();
+        assert_eq!(plan.to_payee, 20);
+        assert_eq!(plan.remaining, 30);
+        assert_eq!(plan.payments_claimed, 2);
+    }
+
+    #[test]
+    fn stream_withdrawal_pays_every_second() {
+        let escrow = Escrow {
+            payout_mode: PayoutMode::Stream,
+            ..escrow(10, true, 100, 5)
+        };
+
+        let plan = escrow.withdrawal(1_255).unwrap();
+        assert_eq!(plan.to_payee, 25);
+        assert_eq!(plan.remaining, 25);
+        assert_eq!(plan.payments_claimed, 2);
+
+        let escrow = Escrow {
+            amount_released: plan.amount_released,
+            payments_claimed: plan.payments_claimed,
+            ..escrow
+        };
+        assert_eq!(escrow.withdrawal(1_256).unwrap().to_payee, 0);
+        assert_eq!(escrow.withdrawal(1_260).unwrap().to_payee, 1);
+        assert_eq!(escrow.withdrawal(9_999).unwrap().to_payee, 25);
+    }
+
+    #[test]
+    fn stream_cancellation_splits_accrued_escrow() {
+        let escrow = Escrow {
+            payout_mode: PayoutMode::Stream,
+            cancel_penalty: CancelPenalty::None,
+            ..escrow(10, true, 100, 5)
+        };
+
+        let plan = escrow.cancellation(1_333).unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer), (33, 17));
+    }
+
+    fn vesting_grant() -> Escrow {
+        Escrow {
+            payout_mode: PayoutMode::Vesting {
+                cliff_period: 100,
+                vesting_period: 400,
+                granularity: 50,
+            },
+            cancel_penalty: CancelPenalty::None,
+            ..escrow(1_000, false, 0, 1)
+        }
+    }
+
+    #[test]
+    fn vesting_releases_nothing_before_cliff() {
+        let escrow = vesting_grant();
+
+        assert_eq!(escrow.withdrawal(1_099).unwrap().to_payee, 0);
+        assert_eq!(escrow.withdrawal(1_100).unwrap().to_payee, 250);
+    }
+
+    #[test]
+    fn vesting_releases_in_steps_until_end() {
+        let escrow = vesting_grant();
+
+        assert_eq!(escrow.withdrawal(1_149).unwrap().to_payee, 250);
+        assert_eq!(escrow.withdrawal(1_150).unwrap().to_payee, 375);
+        assert_eq!(escrow.withdrawal(1_399).unwrap().to_payee, 875);
+        assert_eq!(escrow.withdrawal(1_400).unwrap().to_payee, 1_000);
+    }
+
+    #[test]
+    fn vesting_cancellation_returns_unvested_grant() {
+        let escrow = vesting_grant();
+
+        let plan = escrow.cancellation(1_220).unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer), (500, 500));
+    }
+
+    #[test]
+    fn milestone_approval_releases_tranche() {
+        let escrow = Escrow {
+            payout_mode: PayoutMode::Milestones,
+            cancel_penalty: CancelPenalty::None,
+            ..escrow(100, false, 0, 1)
+        };
+
+        let plan = escrow.approval(30).unwrap();
+        assert_eq!((plan.to_payee, plan.remaining), (30, 70));
+        assert_eq!(escrow.withdrawal(9_999).unwrap().to_payee, 0);
+        assert!(escrow.approval(101).is_none());
+
+        let escrow = Escrow {
+            amount_released: plan.amount_released,
+            ..escrow
+        };
+        let plan = escrow.cancellation(9_999).unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer), (0, 70));
+    }
+
+    fn deposit_and_installments() -> Escrow {
+        Escrow {
+            payout_mode: PayoutMode::Scheduled,
+            installments: vec![
+                Installment {
+                    offset: 0,
+                    amount: 50,
+                },
+                Installment {
+                    offset: 100,
+                    amount: 20,
+                },
+                Installment {
+                    offset: 300,
+                    amount: 30,
+                },
+            ],
+            cancel_penalty: CancelPenalty::None,
+            ..escrow(100, false, 0, 1)
+        }
+    }
+
+    #[test]
+    fn scheduled_withdrawal_pays_installments_in_order() {
+        let escrow = deposit_and_installments();
+
+        let plan = escrow.withdrawal(1_000).unwrap();
+        assert_eq!((plan.to_payee, plan.payments_claimed), (50, 1));
+
+        let plan = escrow.withdrawal(1_299).unwrap();
+        assert_eq!((plan.to_payee, plan.payments_claimed), (70, 2));
+
+        let escrow = Escrow {
+            amount_released: plan.amount_released,
+            payments_claimed: plan.payments_claimed,
+            ..escrow
+        };
+        let plan = escrow.withdrawal(1_300).unwrap();
+        assert_eq!((plan.to_payee, plan.remaining), (30, 0));
+    }
+
+    #[test]
+    fn scheduled_cancellation_refunds_undue_installments() {
+        let escrow = deposit_and_installments();
+
+        let plan = escrow.cancellation(1_150).unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer), (70, 30));
+    }
+
+    #[test]
+    fn escalation_compounds_per_installment() {
+        let escalation = Escalation::BasisPoints { bps: 1_000 };
+
+        assert_eq!(escalation.total_of(100, 0), Some(0));
+        assert_eq!(escalation.total_of(100, 3), Some(100 + 110 + 121));
+        assert_eq!(escalation.total_of(u64::MAX, 2), None);
+        assert_eq!(Escalation::Flat { step: 5 }.total_of(100, 3), Some(315));
+    }
+
+    #[test]
+    fn escalated_withdrawal_pays_due_installments() {
+        let escrow = Escrow {
+            escalation: Escalation::Flat { step: 5 },
+            ..escrow(100, true, 100, 3)
+        };
+
+        assert_eq!(escrow.total(), Some(315));
+        let plan = escrow.withdrawal(1_250).unwrap();
+        assert_eq!((plan.to_payee, plan.remaining), (205, 110));
+    }
+
+    fn subscription(escalation: Escalation) -> Escrow {
+        Escrow {
+            funding: Funding::Pull,
+            escalation,
+            cancel_penalty: CancelPenalty::None,
+            ..escrow(10, true, 100, 5)
+        }
+    }
+
+    #[test]
+    fn pull_takes_due_installments_the_payer_covers() {
+        let escrow = subscription(Escalation::None);
+
+        let plan = escrow.pull(1_350, 25).unwrap();
+        assert_eq!(
+            (plan.to_payee, plan.payments_claimed, plan.payments_missed),
+            (20, 2, 1)
+        );
+        assert_eq!(plan.remaining, 20);
+
+        let escrow = Escrow {
+            amount_released: plan.amount_released,
+            payments_claimed: plan.payments_claimed,
+            payments_missed: plan.payments_missed,
+            ..escrow
+        };
+        let plan = escrow.pull(1_500, 1_000).unwrap();
+        assert_eq!(
+            (plan.to_payee, plan.payments_claimed, plan.payments_missed),
+            (20, 4, 1)
+        );
+        assert_eq!(plan.remaining, 0);
+    }
+
+    #[test]
+    fn pull_with_retries_leaves_uncovered_installments_overdue() {
+        let escrow = Escrow {
+            dunning: Dunning {
+                grace_period: 3_600,
+                max_retries: 3,
+            },
+            ..subscription(Escalation::None)
+        };
+
+        let plan = escrow.pull(1_350, 15).unwrap();
+        assert_eq!(
+            (plan.to_payee, plan.payments_claimed, plan.payments_missed),
+            (10, 1, 0)
+        );
+        assert_eq!((plan.overdue, plan.remaining), (20, 40));
+    }
+
+    #[test]
+    fn escalated_pull_stops_at_first_uncovered_installment() {
+        let escrow = subscription(Escalation::Flat { step: 5 });
+
+        let plan = escrow.pull(1_300, 26).unwrap();
+        assert_eq!(
+            (plan.to_payee, plan.payments_claimed, plan.payments_missed),
+            (25, 2, 1)
+        );
+        assert_eq!(plan.remaining, 25 + 30);
+    }
+
+    #[test]
+    fn pulled_cancellation_charges_installments_due() {
+        let escrow = Escrow {
+            payments_claimed: 1,
+            amount_released: 10,
+            ..subscription(Escalation::None)
+        };
+
+        let plan = escrow.pulled_cancellation(1_350, 1_000).unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer, plan.remaining), (20, 0, 0));
+        assert_eq!(plan.overdue, 0);
+        assert_eq!(
+            (
+                plan.amount_released,
+                plan.payments_claimed,
+                plan.payments_missed
+            ),
+            (30, 3, 0)
+        );
+    }
+
+    #[test]
+    fn pulled_cancellation_misses_what_the_payer_can_not_cover() {
+        let plan = subscription(Escalation::None)
+            .pulled_cancellation(1_350, 15)
+            .unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer, plan.remaining), (10, 0, 0));
+        assert_eq!((plan.payments_claimed, plan.payments_missed), (1, 2));
+        assert_eq!(plan.overdue, 20);
+
+        // overdue installments of a past due payable are not retried anymore
+        let escrow = Escrow {
+            dunning: Dunning {
+                grace_period: 3_600,
+                max_retries: 3,
+            },
+            ..subscription(Escalation::None)
+        };
+        let plan = escrow.pulled_cancellation(1_350, 0).unwrap();
+        assert_eq!((plan.to_payee, plan.overdue), (0, 30));
+        assert_eq!((plan.payments_claimed, plan.payments_missed), (0, 3));
+    }
+
+    #[test]
+    fn reduction_refunds_dropped_installments() {
+        let escrow = Escrow {
+            payments_claimed: 1,
+            amount_released: 10,
+            ..escrow(10, true, 100, 5)
+        };
+
+        let plan = escrow.reduction(1_050, 3).unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer, plan.remaining), (0, 20, 20));
+
+        // half of the dropped escrow is kept as penalty once the cancel period is over
+        let plan = escrow.reduction(1_150, 3).unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer, plan.remaining), (10, 10, 20));
+        assert_eq!((plan.penalty, plan.amount_released), (10, 10));
+    }
+
+    #[test]
+    fn cancellation_before_deadline_refunds_payer() {
+        let escrow = escrow(10, false, 0, 1);
+
+        let plan = escrow.cancellation(1_050).unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer), (0, 10));
+    }
+
+    #[test]
+    fn cancellation_after_deadline_pays_penalty_on_undue_escrow() {
+        let escrow = escrow(11, true, 100, 3);
+
+        let plan = escrow.cancellation(1_150).unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer), (11 + 11, 11));
+    }
+
+    #[test]
+    fn cancellation_without_penalty_refunds_undue_escrow() {
+        let escrow = Escrow {
+            cancel_penalty: CancelPenalty::None,
+            ..escrow(11, true, 100, 3)
+        };
+
+        let plan = escrow.cancellation(1_150).unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer), (11, 22));
+    }
+
+    #[test]
+    fn basis_points_penalty_rounds_down() {
+        let penalty = CancelPenalty::BasisPoints { bps: 3_333 };
+
+        assert_eq!(penalty.applied_to(999, 0), 332);
+        assert_eq!(penalty.applied_to(3, 0), 0);
+        assert_eq!(penalty.applied_to(u64::MAX, 0), 6_148_299_799_767_393_553);
+    }
+
+    #[test]
+    fn flat_penalty_is_capped_by_escrow() {
+        let penalty = CancelPenalty::Flat { amount: 50 };
+
+        assert_eq!(penalty.applied_to(80, 0), 50);
+        assert_eq!(penalty.applied_to(20, 0), 20);
+    }
+
+    #[test]
+    fn step_curve_keeps_fee_of_last_point_reached() {
+        let points = decaying_curve();
+        let bps_at = |elapsed| FeeInterpolation::Step.bps_at(&points, elapsed);
+
+        assert_eq!(bps_at(0), 10_000);
+        assert_eq!(bps_at(199), 10_000);
+        assert_eq!(bps_at(200), 5_000);
+        assert_eq!(bps_at(399), 5_000);
+        assert_eq!(bps_at(1_000), 0);
+    }
+
+    #[test]
+    fn linear_curve_ramps_between_points() {
+        let points = decaying_curve();
+        let bps_at = |elapsed| FeeInterpolation::Linear.bps_at(&points, elapsed);
+
+        assert_eq!(bps_at(0), 10_000);
+        assert_eq!(bps_at(150), 7_500);
+        assert_eq!(bps_at(200), 5_000);
+        assert_eq!(bps_at(301), 2_475);
+        assert_eq!(bps_at(1_000), 0);
+    }
+
+    #[test]
+    fn cancellation_applies_curve_at_cancel_time() {
+        let escrow = Escrow {
+            cancel_penalty: CancelPenalty::Curve {
+                interpolation: FeeInterpolation::Linear,
+                points: decaying_curve(),
+            },
+            ..escrow(100, false, 0, 1)
+        };
+
+        let plan = escrow.cancellation(1_150).unwrap();
+        assert_eq!((plan.to_payee, plan.to_payer, plan.penalty), (75, 25, 75));
+    }
+
+    proptest! {
+        #[test]
+        fn penalty_never_exceeds_balance(
+            penalty in any_cancel_penalty(),
+            balance in any::<u64>(),
+            elapsed in any::<u64>(),
+        ) {
+            prop_assert!(penalty.applied_to(balance, elapsed) <= balance);
+        }
+
+        #[test]
+        fn curve_stays_within_its_points(
+            interpolation in prop_oneof![Just(FeeInterpolation::Step), Just(FeeInterpolation::Linear)],
+            points in any_fee_points(),
+            elapsed in any::<u64>(),
+        ) {
+            let bps = interpolation.bps_at(&points, elapsed);
+            let lowest = points.iter().map(|point| point.bps).min().unwrap();
+            let highest = points.iter().map(|point| point.bps).max().unwrap();
+
+            prop_assert!(lowest <= bps && bps <= highest);
+        }
+
+        #[test]
+        fn withdrawal_never_exceeds_escrow(escrow in any_escrow(), elapsed in 0..=2_000_000_000i64) {
+            let balance = escrow.balance().unwrap();
+            let plan = escrow.withdrawal(escrow.schedule.start_time + elapsed).unwrap();
+
+            prop_assert_eq!(plan.to_payer, 0);
+            prop_assert_eq!(plan.to_payee + plan.remaining, balance);
+            prop_assert!(plan.payments_claimed <= escrow.payment_count());
+        }
+
+        #[test]
+        fn cancellation_splits_whole_escrow(escrow in any_escrow(), elapsed in 0..=2_000_000_000i64) {
+            let balance = escrow.balance().unwrap();
+            let plan = escrow.cancellation(escrow.schedule.start_time + elapsed).unwrap();
+
+            prop_assert_eq!(plan.remaining, 0);
+            prop_assert_eq!(plan.to_payee + plan.to_payer, balance);
+            prop_assert!(plan.payments_claimed <= escrow.payment_count());
+        }
+
+        #[test]
+        fn pull_never_takes_more_than_available(
+            escrow in any_escrow().prop_map(|escrow| Escrow {
+                recurrent: true,
+                payout_mode: PayoutMode::Installments,
+                funding: Funding::Pull,
+                installments: Vec::new(),
+                ..escrow
+            }),
+            pulls in proptest::collection::vec((0..=10_000_000i64, any::<u64>()), 0..8),
+        ) {
+            let mut escrow = escrow;
+            let mut now = escrow.schedule.start_time;
+
+            for (step, available) in pulls {
+                now += step;
+                let plan = escrow.pull(now, available).unwrap();
+                prop_assert!(plan.to_payee <= available);
+
+                escrow.amount_released = plan.amount_released;
+                escrow.payments_claimed = plan.payments_claimed;
+                escrow.payments_missed = plan.payments_missed;
+                let handled = escrow.payments_claimed + escrow.payments_missed;
+                prop_assert!(handled <= escrow.payment_count());
+                prop_assert_eq!(
+                    plan.remaining,
+                    escrow.total().unwrap() - escrow.escalation.total_of(escrow.amount, handled).unwrap()
+                );
+            }
+        }
+
+        #[test]
+        fn payee_never_receives_more_than_agreed(
+            mut escrow in any_escrow(),
+            steps in proptest::collection::vec(0..=10_000_000i64, 0..8),
+            cancel_after in 0..=10_000_000i64,
+        ) {
+            let total = escrow.balance().unwrap();
+            let mut now = escrow.schedule.start_time;
+            let mut paid_to_payee = 0;
+
+            for step in steps {
+                now += step;
+                let plan = escrow.withdrawal(now).unwrap();
+                paid_to_payee += plan.to_payee;
+                escrow.amount_released = plan.amount_released;
+                escrow.payments_claimed = plan.payments_claimed;
+                prop_assert_eq!(plan.remaining, escrow.balance().unwrap());
+            }
+
+            let plan = escrow.cancellation(now + cancel_after).unwrap();
+            paid_to_payee += plan.to_payee;
+
+            prop_assert!(paid_to_payee <= total);
+            prop_assert_eq!(paid_to_payee + plan.to_payer, total);
+        }
+    }
+}
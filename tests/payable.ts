@@ -2,7 +2,7 @@ import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { Payable } from "../target/types/payable";
 import { Connection, Keypair, LAMPORTS_PER_SOL, PublicKey, Signer, SystemProgram } from "@solana/web3.js";
-import { createMint, getOrCreateAssociatedTokenAccount, mintTo, TOKEN_PROGRAM_ID, transfer } from "@solana/spl-token";
+import { approve, ASSOCIATED_TOKEN_PROGRAM_ID, createMint, getAccount, getAssociatedTokenAddressSync, getOrCreateAssociatedTokenAccount, mintTo, TOKEN_PROGRAM_ID, transfer } from "@solana/spl-token";
 
 const TestProgram = async () => {
   // Configure the client to use the local cluster.
@@ -42,14 +42,22 @@ const TestProgram = async () => {
     program.programId
   )
 
-  const [payablePDA, _f] = PublicKey.findProgramAddressSync(
+  // payables are keyed by their creator, their payer and the creator's invoice id
+  const payablePda = (invoiceId: anchor.BN) => PublicKey.findProgramAddressSync(
     [
       anchor.utils.bytes.utf8.encode("payable"),
       payee.publicKey.toBuffer(),
       payer.publicKey.toBuffer(),
+      invoiceId.toArrayLike(Buffer, "le", 8),
     ],
     program.programId
-  )
+  )[0];
+  // the payer accepts the payable they reviewed, not a later one under the same invoice id
+  const payableIdx = async (payable: PublicKey) => (await program.account.payable.fetch(payable)).payableIdx;
+
+  const halfPaymentPenalty = { basisPoints: { bps: 5000 } };
+  const invoiceId = new anchor.BN(1);
+  const payablePDA = payablePda(invoiceId);
 
   await provider.connection.confirmTransaction(
     await provider.connection.requestAirdrop(
@@ -119,7 +127,8 @@ const TestProgram = async () => {
 
   const payeeAta = await getOrCreateAssociatedTokenAccount(connection, payer, token, payee.publicKey);
   const payerAta = await getOrCreateAssociatedTokenAccount(connection, payer, token, payer.publicKey);
-  const payableAta = await getOrCreateAssociatedTokenAccount(connection, payer, token, payablePDA, true);
+  // vault is created by the program on acceptance
+  const payableAta = { address: getAssociatedTokenAddressSync(token, payablePDA, true) };
 
   // transfer some token to payer 
   await transfer(
@@ -128,7 +137,7 @@ const TestProgram = async () => {
     tokenAccount.address,
     payerAta.address,
     mint.publicKey,
-    100 
+    1000
   );
 
   console.log("-----------------------ADMIN ADDRESS: ", admin.publicKey.toBase58());
@@ -152,11 +161,12 @@ const TestProgram = async () => {
 
   console.log("-----------------------STARTING PAYABLE CREATION--------------------------");
   const createPayableTx = await program.methods.createPayable(
-    new anchor.BN(1),
-    false,
-    new anchor.BN(1),
-    new anchor.BN(1),
-    new anchor.BN(1)
+    invoiceId,
+    terms({
+      amount: new anchor.BN(1),
+      cancelPeriod: new anchor.BN(1),
+      cancelPenalty: halfPaymentPenalty,
+    })
   ).accounts({
     counter: counterPDA,
     payable: payablePDA,
@@ -167,9 +177,334 @@ const TestProgram = async () => {
   }).signers([payeeSig]).rpc()
   console.log("-----------------------PAYABLE CREATION SUCCESSFUL:", createPayableTx.toString());
 
+  console.log("-----------------------STARTING CYCLIC PAYABLE CREATION--------------------------");
+  const cyclicInvoiceId = new anchor.BN(99);
+  const [cyclicPayablePDA, _c] = PublicKey.findProgramAddressSync(
+    [
+      anchor.utils.bytes.utf8.encode("payable"),
+      payee.publicKey.toBuffer(),
+      payee.publicKey.toBuffer(),
+      cyclicInvoiceId.toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  )
+  await expectError(
+    program.methods.createPayable(
+      cyclicInvoiceId,
+      terms({
+        amount: new anchor.BN(1),
+        cancelPeriod: new anchor.BN(1),
+        cancelPenalty: halfPaymentPenalty,
+      })
+    ).accounts({
+      counter: counterPDA,
+      payable: cyclicPayablePDA,
+      signer: payee.publicKey,
+      payer: payee.publicKey,
+      validTokenMint: token,
+      systemProgram: SystemProgram.programId
+    }).signers([payeeSig]).rpc(),
+    "CyclicPayable"
+  );
+  console.log("-----------------------CYCLIC PAYABLE CREATION REJECTED");
+
+  console.log("-----------------------STARTING INVALID PAYABLE CREATION--------------------------");
+  const invalidInvoiceId = new anchor.BN(98);
+  const invalidPayablePDA = payablePda(invalidInvoiceId);
+  const invalidTerms: [number, boolean, number, number, string][] = [
+    [0, false, 1, 0, "ZeroAmount"],
+    [1, true, 0, 1, "ZeroPaymentCount"],
+    [1, true, 3, 0, "ZeroPaymentInterval"],
+    [1, false, 3, 0, "InvalidOneOffPaymentCount"],
+    [1, false, 1, 1, "InvalidOneOffPaymentInterval"],
+  ];
+  for (const [amount, recurrent, count, interval, code] of invalidTerms) {
+    await expectError(
+      program.methods.createPayable(
+        invalidInvoiceId,
+        terms({
+          amount: new anchor.BN(amount),
+          recurrent: recurrent,
+          numberOfRecurrentPayment: new anchor.BN(count),
+          recurrentPaymentInterval: new anchor.BN(interval),
+          cancelPeriod: new anchor.BN(1),
+          cancelPenalty: halfPaymentPenalty,
+        })
+      ).accounts({
+        counter: counterPDA,
+        payable: invalidPayablePDA,
+        signer: payee.publicKey,
+        payer: payer.publicKey,
+        validTokenMint: token,
+        systemProgram: SystemProgram.programId
+      }).signers([payeeSig]).rpc(),
+      code
+    );
+  }
+  console.log("-----------------------INVALID PAYABLE CREATION REJECTED");
+
+  console.log("-----------------------STARTING CONCURRENT PAYABLE CREATION--------------------------");
+  const secondInvoiceId = new anchor.BN(2);
+  const secondPayablePDA = payablePda(secondInvoiceId);
+  const createSecondPayableTx = await program.methods.createPayable(
+    secondInvoiceId,
+    terms({
+      amount: new anchor.BN(1),
+      recurrent: true,
+      numberOfRecurrentPayment: new anchor.BN(3),
+      recurrentPaymentInterval: new anchor.BN(1),
+      cancelPeriod: new anchor.BN(1),
+      cancelPenalty: halfPaymentPenalty,
+    })
+  ).accounts({
+    counter: counterPDA,
+    payable: secondPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  const firstPayable = await program.account.payable.fetch(payablePDA);
+  const secondPayable = await program.account.payable.fetch(secondPayablePDA);
+  if (firstPayable.invoiceId.eq(secondPayable.invoiceId) || firstPayable.payableIdx.eq(secondPayable.payableIdx)) {
+    throw new Error("concurrent payables must not share an invoice id or index");
+  }
+  console.log("-----------------------CONCURRENT PAYABLE CREATION SUCCESSFUL:", createSecondPayableTx.toString());
+
+  console.log("-----------------------STARTING PAYABLE REJECTION--------------------------");
+  const rejectedInvoiceId = new anchor.BN(3);
+  const rejectedPayablePDA = payablePda(rejectedInvoiceId);
+  await program.methods.createPayable(
+    rejectedInvoiceId,
+    terms({
+      amount: new anchor.BN(5),
+      cancelPeriod: new anchor.BN(1),
+      cancelPenalty: halfPaymentPenalty,
+    })
+  ).accounts({
+    counter: counterPDA,
+    payable: rejectedPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  const payeeBalanceBeforeRejection = await connection.getBalance(payee.publicKey);
+  const rejectPayableTx = await program.methods.rejectPayable(
+    1
+  ).accounts({
+    payable: rejectedPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  if (await program.account.payable.fetchNullable(rejectedPayablePDA) !== null) {
+    throw new Error("rejected payable must be closed");
+  }
+  if (await connection.getBalance(payee.publicKey) <= payeeBalanceBeforeRejection) {
+    throw new Error("rejected payable rent must be returned to the payee");
+  }
+  console.log("-----------------------PAYABLE REJECTION SUCCESSFUL:", rejectPayableTx.toString());
+
+  console.log("-----------------------STARTING EXPIRED PAYABLE ACCEPTANCE--------------------------");
+  const expiredInvoiceId = new anchor.BN(4);
+  const expiredPayablePDA = payablePda(expiredInvoiceId);
+  await program.methods.createPayable(
+    expiredInvoiceId,
+    terms({
+      amount: new anchor.BN(1),
+      cancelPeriod: new anchor.BN(1),
+      cancelPenalty: halfPaymentPenalty,
+      acceptancePeriod: new anchor.BN(1),
+    })
+  ).accounts({
+    counter: counterPDA,
+    payable: expiredPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  await sleep(3000);
+  await expectError(
+    program.methods.acceptPayable(
+      await payableIdx(expiredPayablePDA),
+      0,
+      false,
+      halfPaymentPenalty
+    ).accounts({
+      payable: expiredPayablePDA,
+      signer: payer.publicKey,
+      payee: payee.publicKey,
+      validTokenMint: token,
+      payerAta: payerAta.address,
+      payableAta: getAssociatedTokenAddressSync(token, expiredPayablePDA, true),
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId
+    }).signers([payerSig]).rpc(),
+    "AcceptanceDeadlinePassed"
+  );
+  // terms re-created under the expired invoice id get a new revision
+  await program.methods.createPayable(
+    expiredInvoiceId,
+    terms({
+      amount: new anchor.BN(2),
+      cancelPeriod: new anchor.BN(1),
+      cancelPenalty: halfPaymentPenalty,
+      acceptancePeriod: new anchor.BN(1),
+    })
+  ).accounts({
+    counter: counterPDA,
+    payable: expiredPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  const recreatedPayable = await program.account.payable.fetch(expiredPayablePDA);
+  if (recreatedPayable.revision !== 1) {
+    throw new Error(`expected the re-created payable at revision 1, got ${recreatedPayable.revision}`);
+  }
+  await expectError(
+    program.methods.acceptPayable(
+      await payableIdx(expiredPayablePDA),
+      0,
+      false,
+      halfPaymentPenalty
+    ).accounts({
+      payable: expiredPayablePDA,
+      signer: payer.publicKey,
+      payee: payee.publicKey,
+      validTokenMint: token,
+      payerAta: payerAta.address,
+      payableAta: getAssociatedTokenAddressSync(token, expiredPayablePDA, true),
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId
+    }).signers([payerSig]).rpc(),
+    "RevisionMismatch"
+  );
+  await sleep(3000);
+  const closeExpiredPayableTx = await program.methods.closePayable().accounts({
+    payable: expiredPayablePDA,
+    signer: payee.publicKey,
+    payee: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    payerAta: null,
+    payableAta: null,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  if (await program.account.payable.fetchNullable(expiredPayablePDA) !== null) {
+    throw new Error("expired payable must be closed");
+  }
+  console.log("-----------------------EXPIRED PAYABLE CLOSED:", closeExpiredPayableTx.toString());
+  // once closed, the invoice id starts over at revision 0 under a new payable index, so an
+  // acceptance of the closed payable can not accept the new terms
+  await program.methods.createPayable(
+    expiredInvoiceId,
+    terms({
+      amount: new anchor.BN(3),
+      cancelPenalty: halfPaymentPenalty,
+    })
+  ).accounts({
+    counter: counterPDA,
+    payable: expiredPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  const reopenedPayable = await program.account.payable.fetch(expiredPayablePDA);
+  if (reopenedPayable.revision !== 0 || reopenedPayable.payableIdx.eq(recreatedPayable.payableIdx)) {
+    throw new Error("expected the re-opened payable at revision 0 under a new payable index");
+  }
+  await expectError(
+    program.methods.acceptPayable(
+      recreatedPayable.payableIdx,
+      0,
+      false,
+      halfPaymentPenalty
+    ).accounts({
+      payable: expiredPayablePDA,
+      signer: payer.publicKey,
+      payee: payee.publicKey,
+      validTokenMint: token,
+      payerAta: payerAta.address,
+      payableAta: getAssociatedTokenAddressSync(token, expiredPayablePDA, true),
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId
+    }).signers([payerSig]).rpc(),
+    "PayableMismatch"
+  );
+  console.log("-----------------------RE-OPENED PAYABLE ACCEPTANCE REJECTED");
+
+  console.log("-----------------------STARTING PAYABLE ACCEPTANCE WITH WRONG PENALTY--------------------------");
+  await expectError(
+    program.methods.acceptPayable(
+      await payableIdx(payablePDA),
+      0,
+      false,
+      { none: {} }
+    ).accounts({
+      payable: payablePDA,
+      signer: payer.publicKey,
+      payee: payee.publicKey,
+      validTokenMint: token,
+      payerAta: payerAta.address,
+      payableAta: payableAta.address,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId
+    }).signers([payerSig]).rpc(),
+    "CancelPenaltyMismatch"
+  );
+  console.log("-----------------------PAYABLE ACCEPTANCE WITH WRONG PENALTY REJECTED");
+
+  console.log("-----------------------STARTING PAYABLE AMENDMENT--------------------------");
+  const amendPayableTx = await program.methods.amendPayable(
+    terms({
+      amount: new anchor.BN(1),
+      cancelPeriod: new anchor.BN(1),
+      cancelPenalty: halfPaymentPenalty,
+    })
+  ).accounts({
+    payable: payablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  // the payer reviewed the terms before the amendment
+  await expectError(
+    program.methods.acceptPayable(
+      await payableIdx(payablePDA),
+      0,
+      false,
+      halfPaymentPenalty
+    ).accounts({
+      payable: payablePDA,
+      signer: payer.publicKey,
+      payee: payee.publicKey,
+      validTokenMint: token,
+      payerAta: payerAta.address,
+      payableAta: payableAta.address,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId
+    }).signers([payerSig]).rpc(),
+    "RevisionMismatch"
+  );
+  console.log("-----------------------PAYABLE AMENDMENT SUCCESSFUL:", amendPayableTx.toString());
+
   console.log("-----------------------STARTING PAYABLE ACCEPTANCE--------------------------");
   const acceptPayableTx = await program.methods.acceptPayable(
-    false
+    await payableIdx(payablePDA),
+    1,
+    false,
+    halfPaymentPenalty
   ).accounts({
     payable: payablePDA,
     signer: payer.publicKey,
@@ -178,9 +513,73 @@ const TestProgram = async () => {
     payerAta: payerAta.address,
     payableAta: payableAta.address,
     tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
     systemProgram: SystemProgram.programId
   }).signers([payerSig]).rpc()
   console.log("-----------------------PAYABLE ACCEPTANCE SUCCESSFUL:", acceptPayableTx.toString());
+
+  console.log("-----------------------STARTING MISROUTED WITHDRAWAL--------------------------");
+  await expectError(
+    program.methods.withdraw().accounts({
+      payable: payablePDA,
+      signer: payee.publicKey,
+      payer: payer.publicKey,
+      validTokenMint: token,
+      payeeAta: payerAta.address,
+      payerAta: null,
+      payableAta: payableAta.address,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId
+    }).signers([payeeSig]).rpc(),
+    "InvalidPayeeAtaOwner"
+  );
+  console.log("-----------------------MISROUTED WITHDRAWAL REJECTED");
+
+  console.log("-----------------------STARTING FOREIGN VAULT WITHDRAWAL--------------------------");
+  const foreignVault = await getOrCreateAssociatedTokenAccount(connection, payee, token, payee.publicKey);
+  await expectError(
+    program.methods.withdraw().accounts({
+      payable: payablePDA,
+      signer: payee.publicKey,
+      payer: payer.publicKey,
+      validTokenMint: token,
+      payeeAta: payeeAta.address,
+      payerAta: null,
+      payableAta: foreignVault.address,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId
+    }).signers([payeeSig]).rpc(),
+    "ConstraintTokenOwner"
+  );
+  console.log("-----------------------FOREIGN VAULT WITHDRAWAL REJECTED");
+
+  console.log("-----------------------STARTING ACCEPTED PAYABLE OVERWRITE--------------------------");
+  await expectError(
+    program.methods.createPayable(
+      invoiceId,
+      terms({
+        amount: new anchor.BN(100),
+        recurrent: true,
+        numberOfRecurrentPayment: new anchor.BN(10),
+        recurrentPaymentInterval: new anchor.BN(1),
+        cancelPeriod: new anchor.BN(1_000_000),
+        cancelPenalty: halfPaymentPenalty,
+      })
+    ).accounts({
+      counter: counterPDA,
+      payable: payablePDA,
+      signer: payee.publicKey,
+      payer: payer.publicKey,
+      validTokenMint: token,
+      systemProgram: SystemProgram.programId
+    }).signers([payeeSig]).rpc(),
+    "PayableInProgress"
+  );
+  const acceptedPayable = await program.account.payable.fetch(payablePDA);
+  if (!acceptedPayable.amount.eq(new anchor.BN(1)) || acceptedPayable.recurrent) {
+    throw new Error("accepted payable terms must not change");
+  }
+  console.log("-----------------------ACCEPTED PAYABLE OVERWRITE REJECTED");
   
   console.log("-----------------------STARTING PAYABLE CANCELATION--------------------------");
   const cancelPayableTx = await program.methods.cancelPayable().accounts({
@@ -195,22 +594,789 @@ const TestProgram = async () => {
     systemProgram: SystemProgram.programId
   }).signers([payerSig]).rpc()
   console.log("-----------------------PAYABLE CANCELATION SUCCESSFUL:", cancelPayableTx.toString());
-  
+  if (await program.account.payable.fetchNullable(payablePDA) !== null) {
+    throw new Error("cancelled payable must be closed");
+  }
+  if (await connection.getAccountInfo(payableAta.address) !== null) {
+    throw new Error("cancelled payable vault must be closed");
+  }
+
+  console.log("-----------------------STARTING FEE CURVE PAYABLE CANCELATION--------------------------");
+  const curveInvoiceId = new anchor.BN(5);
+  const curvePayablePDA = payablePda(curveInvoiceId);
+  const curveTerms = (points: { offset: anchor.BN, bps: number }[]) => terms({
+    cancelPenalty: { curve: { interpolation: { step: {} }, points } },
+  });
+  const decayingFee = [
+    { offset: new anchor.BN(0), bps: 2500 },
+    { offset: new anchor.BN(3600), bps: 1000 },
+  ];
+  await expectError(
+    program.methods.createPayable(
+      curveInvoiceId,
+      curveTerms([...decayingFee].reverse())
+    ).accounts({
+      counter: counterPDA,
+      payable: curvePayablePDA,
+      signer: payee.publicKey,
+      payer: payer.publicKey,
+      validTokenMint: token,
+      systemProgram: SystemProgram.programId
+    }).signers([payeeSig]).rpc(),
+    "InvalidCancelFeeCurve"
+  );
+  await program.methods.createPayable(
+    curveInvoiceId,
+    curveTerms(decayingFee)
+  ).accounts({
+    counter: counterPDA,
+    payable: curvePayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  const curvePayableAta = getAssociatedTokenAddressSync(token, curvePayablePDA, true);
+  await program.methods.acceptPayable(
+    await payableIdx(curvePayablePDA),
+    0,
+    false,
+    curveTerms(decayingFee).cancelPenalty
+  ).accounts({
+    payable: curvePayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: curvePayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  const payeeBalanceBeforeCurveCancel = Number((await getAccount(connection, payeeAta.address)).amount);
+  const cancelCurvePayableTx = await program.methods.cancelPayable().accounts({
+    payable: curvePayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payeeAta: payeeAta.address,
+    payerAta: payerAta.address,
+    payableAta: curvePayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  // 25% of 10 tokens rounds down to 2 within the first hour
+  const curveFee = Number((await getAccount(connection, payeeAta.address)).amount) - payeeBalanceBeforeCurveCancel;
+  if (curveFee !== 2) {
+    throw new Error(`expected a cancel fee of 2, got ${curveFee}`);
+  }
+  console.log("-----------------------FEE CURVE PAYABLE CANCELATION SUCCESSFUL:", cancelCurvePayableTx.toString());
+
+  console.log("-----------------------STARTING RECURRENT PAYABLE ACCEPTANCE--------------------------");
+  const secondPayableAta = { address: getAssociatedTokenAddressSync(token, secondPayablePDA, true) };
+  const acceptSecondPayableTx = await program.methods.acceptPayable(
+    await payableIdx(secondPayablePDA),
+    0,
+    true,
+    halfPaymentPenalty
+  ).accounts({
+    payable: secondPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: secondPayableAta.address,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  console.log("-----------------------RECURRENT PAYABLE ACCEPTANCE SUCCESSFUL:", acceptSecondPayableTx.toString());
+
   console.log("-----------------------STARTING PAYABLE WITHDRAWAL--------------------------");
-  sleep(3000);
+  await sleep(3000);
   const withdrawTx = await program.methods.withdraw().accounts({
-    payable: payablePDA,
+    payable: secondPayablePDA,
     signer: payee.publicKey,
     payer: payer.publicKey,
     validTokenMint: token,
     payeeAta: payeeAta.address,
-    payableAta: payableAta.address,
+    payerAta: null,
+    payableAta: secondPayableAta.address,
     tokenProgram: TOKEN_PROGRAM_ID,
     systemProgram: SystemProgram.programId
   }).signers([payeeSig]).rpc()
+  if (await program.account.payable.fetchNullable(secondPayablePDA) !== null) {
+    throw new Error("fully withdrawn payable must be closed");
+  }
   console.log("-----------------------PAYABLE WITHDRAWAL SUCCESSFUL:", withdrawTx.toString());
+
+  console.log("-----------------------STARTING STREAMING PAYABLE WITHDRAWAL--------------------------");
+  const streamInvoiceId = new anchor.BN(6);
+  const streamPayablePDA = payablePda(streamInvoiceId);
+  await program.methods.createPayable(
+    streamInvoiceId,
+    terms({
+      recurrent: true,
+      numberOfRecurrentPayment: new anchor.BN(3),
+      recurrentPaymentInterval: new anchor.BN(10),
+      payoutMode: { stream: {} },
+    })
+  ).accounts({
+    counter: counterPDA,
+    payable: streamPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  const streamPayableAta = getAssociatedTokenAddressSync(token, streamPayablePDA, true);
+  await program.methods.acceptPayable(
+    await payableIdx(streamPayablePDA),
+    0,
+    true,
+    { none: {} }
+  ).accounts({
+    payable: streamPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: streamPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  await sleep(3000);
+  const streamWithdrawTx = await program.methods.withdraw().accounts({
+    payable: streamPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    payeeAta: payeeAta.address,
+    payerAta: null,
+    payableAta: streamPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  // one token accrues every second, well before the first whole installment
+  const streamPayable = await program.account.payable.fetch(streamPayablePDA);
+  if (streamPayable.amountReleased.lten(0) || streamPayable.amountReleased.gten(10)) {
+    throw new Error(`expected a partial stream withdrawal, got ${streamPayable.amountReleased}`);
+  }
+  console.log("-----------------------STREAMING PAYABLE WITHDRAWAL SUCCESSFUL:", streamWithdrawTx.toString());
+
+  console.log("-----------------------STARTING VESTING PAYABLE CANCELATION--------------------------");
+  const vestingInvoiceId = new anchor.BN(7);
+  const vestingPayablePDA = payablePda(vestingInvoiceId);
+  const vestingPayoutMode = {
+    vesting: {
+      cliffPeriod: new anchor.BN(3600),
+      vestingPeriod: new anchor.BN(4 * 3600),
+      granularity: new anchor.BN(60),
+    }
+  };
+  // a grant is one-off, recurrent installments would only multiply it
+  await expectError(
+    program.methods.createPayable(
+      vestingInvoiceId,
+      terms({
+        amount: new anchor.BN(20),
+        recurrent: true,
+        numberOfRecurrentPayment: new anchor.BN(2),
+        recurrentPaymentInterval: new anchor.BN(3600),
+        payoutMode: vestingPayoutMode,
+      })
+    ).accounts({
+      counter: counterPDA,
+      payable: vestingPayablePDA,
+      signer: payee.publicKey,
+      payer: payer.publicKey,
+      validTokenMint: token,
+      systemProgram: SystemProgram.programId
+    }).signers([payeeSig]).rpc(),
+    "InvalidVestingSchedule"
+  );
+  await program.methods.createPayable(
+    vestingInvoiceId,
+    terms({
+      amount: new anchor.BN(20),
+      payoutMode: vestingPayoutMode,
+    })
+  ).accounts({
+    counter: counterPDA,
+    payable: vestingPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  const vestingPayableAta = getAssociatedTokenAddressSync(token, vestingPayablePDA, true);
+  await program.methods.acceptPayable(
+    await payableIdx(vestingPayablePDA),
+    0,
+    false,
+    { none: {} }
+  ).accounts({
+    payable: vestingPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: vestingPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  await expectError(
+    program.methods.withdraw().accounts({
+      payable: vestingPayablePDA,
+      signer: payee.publicKey,
+      payer: payer.publicKey,
+      validTokenMint: token,
+      payeeAta: payeeAta.address,
+      payerAta: null,
+      payableAta: vestingPayableAta,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId
+    }).signers([payeeSig]).rpc(),
+    "NothingDue"
+  );
+  // nothing has vested before the cliff, so the whole grant returns to the payer
+  const payerBalanceBeforeVestingCancel = Number((await getAccount(connection, payerAta.address)).amount);
+  const cancelVestingPayableTx = await program.methods.cancelPayable().accounts({
+    payable: vestingPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payeeAta: payeeAta.address,
+    payerAta: payerAta.address,
+    payableAta: vestingPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  const vestingRefund = Number((await getAccount(connection, payerAta.address)).amount) - payerBalanceBeforeVestingCancel;
+  if (vestingRefund !== 20) {
+    throw new Error(`expected the unvested grant of 20 to be refunded, got ${vestingRefund}`);
+  }
+  console.log("-----------------------VESTING PAYABLE CANCELATION SUCCESSFUL:", cancelVestingPayableTx.toString());
+
+  console.log("-----------------------STARTING MILESTONE PAYABLE APPROVAL--------------------------");
+  const milestoneInvoiceId = new anchor.BN(8);
+  const milestonePayablePDA = payablePda(milestoneInvoiceId);
+  const milestone = (amount: number, deliverable: string) => ({
+    amount: new anchor.BN(amount),
+    descriptionHash: Array.from(Buffer.from(anchor.utils.sha256.hash(deliverable), "hex")),
+    approved: false,
+  });
+  await program.methods.createPayable(
+    milestoneInvoiceId,
+    terms({
+      amount: new anchor.BN(30),
+      payoutMode: { milestones: {} },
+      milestones: [milestone(10, "design"), milestone(20, "delivery")],
+    })
+  ).accounts({
+    counter: counterPDA,
+    payable: milestonePayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  const milestonePayableAta = getAssociatedTokenAddressSync(token, milestonePayablePDA, true);
+  await program.methods.acceptPayable(
+    await payableIdx(milestonePayablePDA),
+    0,
+    false,
+    { none: {} }
+  ).accounts({
+    payable: milestonePayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: milestonePayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  const approveMilestone = (milestoneIdx: number) => program.methods.approveMilestone(
+    milestoneIdx
+  ).accounts({
+    payable: milestonePayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payeeAta: payeeAta.address,
+    payableAta: milestonePayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc();
+  const payeeBalanceBeforeApproval = Number((await getAccount(connection, payeeAta.address)).amount);
+  const approveMilestoneTx = await approveMilestone(0);
+  const approvedTranche = Number((await getAccount(connection, payeeAta.address)).amount) - payeeBalanceBeforeApproval;
+  if (approvedTranche !== 10) {
+    throw new Error(`expected the approved tranche of 10 to be released, got ${approvedTranche}`);
+  }
+  await expectError(approveMilestone(0), "MilestoneAlreadyApproved");
+  await expectError(approveMilestone(2), "InvalidMilestone");
+  console.log("-----------------------MILESTONE PAYABLE APPROVAL SUCCESSFUL:", approveMilestoneTx.toString());
+
+  console.log("-----------------------STARTING MILESTONE PAYABLE CANCELATION--------------------------");
+  // a stray token sent to the vault keeps it open after the cancellation
+  await transfer(connection, payer, payerAta.address, milestonePayableAta, payer, 1);
+  const payerBalanceBeforeMilestoneCancel = Number((await getAccount(connection, payerAta.address)).amount);
+  const cancelMilestonePayableTx = await program.methods.cancelPayable().accounts({
+    payable: milestonePayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payeeAta: payeeAta.address,
+    payerAta: payerAta.address,
+    payableAta: milestonePayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  const milestoneRefund = Number((await getAccount(connection, payerAta.address)).amount) - payerBalanceBeforeMilestoneCancel;
+  if (milestoneRefund !== 20) {
+    throw new Error(`expected the unapproved milestone of 20 to be refunded, got ${milestoneRefund}`);
+  }
+  console.log("-----------------------MILESTONE PAYABLE CANCELATION SUCCESSFUL:", cancelMilestonePayableTx.toString());
+
+  console.log("-----------------------STARTING CANCELLED PAYABLE CLOSE--------------------------");
+  const closeMilestonePayable = (payableAta: PublicKey | null) => program.methods.closePayable().accounts({
+    payable: milestonePayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: payableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc();
+  await expectError(closeMilestonePayable(null), "MissingVault");
+  const closeMilestonePayableTx = await closeMilestonePayable(milestonePayableAta);
+  if (await connection.getAccountInfo(milestonePayableAta) !== null) {
+    throw new Error("cancelled payable vault must be swept and closed");
+  }
+  console.log("-----------------------CANCELLED PAYABLE CLOSE SUCCESSFUL:", closeMilestonePayableTx.toString());
+
+  console.log("-----------------------STARTING SCHEDULED PAYABLE WITHDRAWAL--------------------------");
+  const scheduledInvoiceId = new anchor.BN(9);
+  const scheduledPayablePDA = payablePda(scheduledInvoiceId);
+  await program.methods.createPayable(
+    scheduledInvoiceId,
+    terms({
+      amount: new anchor.BN(30),
+      payoutMode: { scheduled: {} },
+      installments: [
+        { offset: new anchor.BN(0), amount: new anchor.BN(10) },
+        { offset: new anchor.BN(3600), amount: new anchor.BN(20) },
+      ],
+    })
+  ).accounts({
+    counter: counterPDA,
+    payable: scheduledPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  const scheduledPayableAta = getAssociatedTokenAddressSync(token, scheduledPayablePDA, true);
+  await program.methods.acceptPayable(
+    await payableIdx(scheduledPayablePDA),
+    0,
+    false,
+    { none: {} }
+  ).accounts({
+    payable: scheduledPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: scheduledPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  const scheduledWithdrawTx = await program.methods.withdraw().accounts({
+    payable: scheduledPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    payeeAta: payeeAta.address,
+    payerAta: null,
+    payableAta: scheduledPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  // only the deposit is due right after acceptance
+  const scheduledPayable = await program.account.payable.fetch(scheduledPayablePDA);
+  if (!scheduledPayable.amountReleased.eqn(10) || !scheduledPayable.paymentsClaimed.eqn(1)) {
+    throw new Error("expected only the deposit to be withdrawn");
+  }
+  console.log("-----------------------SCHEDULED PAYABLE WITHDRAWAL SUCCESSFUL:", scheduledWithdrawTx.toString());
+
+  console.log("-----------------------STARTING ESCALATING PAYABLE ACCEPTANCE--------------------------");
+  const escalatingInvoiceId = new anchor.BN(10);
+  const escalatingPayablePDA = payablePda(escalatingInvoiceId);
+  const escalatingTerms = (recurrent: boolean) => terms({
+    recurrent: recurrent,
+    numberOfRecurrentPayment: new anchor.BN(recurrent ? 3 : 1),
+    recurrentPaymentInterval: new anchor.BN(recurrent ? 3600 : 0),
+    escalation: { flat: { step: new anchor.BN(5) } },
+  });
+  const createEscalatingPayable = (recurrent: boolean) => program.methods.createPayable(
+    escalatingInvoiceId,
+    escalatingTerms(recurrent)
+  ).accounts({
+    counter: counterPDA,
+    payable: escalatingPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc();
+  await expectError(createEscalatingPayable(false), "InvalidEscalation");
+  await createEscalatingPayable(true);
+  const escalatingPayableAta = getAssociatedTokenAddressSync(token, escalatingPayablePDA, true);
+  const acceptEscalatingPayableTx = await program.methods.acceptPayable(
+    await payableIdx(escalatingPayablePDA),
+    0,
+    true,
+    { none: {} }
+  ).accounts({
+    payable: escalatingPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: escalatingPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  // installments of 10, 15 and 20 are locked up front
+  const escalatedEscrow = Number((await getAccount(connection, escalatingPayableAta)).amount);
+  if (escalatedEscrow !== 45) {
+    throw new Error(`expected an escalated escrow of 45, got ${escalatedEscrow}`);
+  }
+  console.log("-----------------------ESCALATING PAYABLE ACCEPTANCE SUCCESSFUL:", acceptEscalatingPayableTx.toString());
+
+  console.log("-----------------------STARTING CALENDAR PAYABLE WITHDRAWAL--------------------------");
+  const calendarInvoiceId = new anchor.BN(11);
+  const calendarPayablePDA = payablePda(calendarInvoiceId);
+  const calendarTerms = (secondsToFirstDue: number) => terms({
+    recurrent: true,
+    numberOfRecurrentPayment: new anchor.BN(3),
+    billingCycle: {
+      calendar: {
+        period: { monthly: {} },
+        firstDueAt: new anchor.BN(Math.floor(Date.now() / 1000) + secondsToFirstDue),
+      }
+    },
+  });
+  const createCalendarPayable = (invoiceId: anchor.BN, payable: PublicKey, secondsToFirstDue: number) =>
+    program.methods.createPayable(
+      invoiceId,
+      calendarTerms(secondsToFirstDue)
+    ).accounts({
+      counter: counterPDA,
+      payable,
+      signer: payee.publicKey,
+      payer: payer.publicKey,
+      validTokenMint: token,
+      systemProgram: SystemProgram.programId
+    }).signers([payeeSig]).rpc();
+  const acceptCalendarPayable = async (payable: PublicKey) => program.methods.acceptPayable(
+    await payableIdx(payable),
+    0,
+    true,
+    { none: {} }
+  ).accounts({
+    payable,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: getAssociatedTokenAddressSync(token, payable, true),
+    tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc();
+  // months before the payable exists are never billed
+  await expectError(createCalendarPayable(calendarInvoiceId, calendarPayablePDA, -3600), "InvalidBillingCycle");
+  await createCalendarPayable(calendarInvoiceId, calendarPayablePDA, 5);
+  await expectError(
+    program.methods.amendPayable(calendarTerms(-3600)).accounts({
+      payable: calendarPayablePDA,
+      signer: payee.publicKey,
+      payer: payer.publicKey,
+      systemProgram: SystemProgram.programId
+    }).signers([payeeSig]).rpc(),
+    "InvalidBillingCycle"
+  );
+  const calendarPayableAta = getAssociatedTokenAddressSync(token, calendarPayablePDA, true);
+  await acceptCalendarPayable(calendarPayablePDA);
+
+  // a payable accepted after its first calendar payment would back-bill it
+  const lateCalendarInvoiceId = new anchor.BN(14);
+  const lateCalendarPayablePDA = payablePda(lateCalendarInvoiceId);
+  await createCalendarPayable(lateCalendarInvoiceId, lateCalendarPayablePDA, 2);
+  await sleep(6000);
+  await expectError(acceptCalendarPayable(lateCalendarPayablePDA), "CalendarStartPassed");
+  const calendarWithdrawTx = await program.methods.withdraw().accounts({
+    payable: calendarPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    payeeAta: payeeAta.address,
+    payerAta: null,
+    payableAta: calendarPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  // the first bill fell due after acceptance, the next one is a month away
+  const calendarPayable = await program.account.payable.fetch(calendarPayablePDA);
+  if (!calendarPayable.amountReleased.eqn(10) || !calendarPayable.paymentsClaimed.eqn(1)) {
+    throw new Error("expected only the first calendar installment to be withdrawn");
+  }
+  console.log("-----------------------CALENDAR PAYABLE WITHDRAWAL SUCCESSFUL:", calendarWithdrawTx.toString());
+
+  console.log("-----------------------STARTING CALENDAR PAYABLE EXTENSION--------------------------");
+  const extendCalendarPayable = (additionalPayments: number) => program.methods.extendPayable(
+    new anchor.BN(additionalPayments)
+  ).accounts({
+    payable: calendarPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: calendarPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc();
+  await expectError(extendCalendarPayable(0), "InvalidExtension");
+  const extendCalendarPayableTx = await extendCalendarPayable(2);
+  // two more monthly installments of 10 join the 20 still in escrow
+  const extendedPayable = await program.account.payable.fetch(calendarPayablePDA);
+  const extendedEscrow = Number((await getAccount(connection, calendarPayableAta)).amount);
+  if (!extendedPayable.numberOfRecurrentPayment.eqn(5) || extendedEscrow !== 40) {
+    throw new Error(`expected 5 payments and an escrow of 40, got ${extendedEscrow}`);
+  }
+  console.log("-----------------------CALENDAR PAYABLE EXTENSION SUCCESSFUL:", extendCalendarPayableTx.toString());
+
+  console.log("-----------------------STARTING CALENDAR PAYABLE REDUCTION--------------------------");
+  const reduceCalendarPayable = (droppedPayments: number) => program.methods.reducePayable(
+    new anchor.BN(droppedPayments)
+  ).accounts({
+    payable: calendarPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payeeAta: payeeAta.address,
+    payerAta: payerAta.address,
+    payableAta: calendarPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc();
+  // the first installment is already due, so it can not be dropped
+  await expectError(reduceCalendarPayable(4), "InvalidReduction");
+  const payerBalanceBeforeReduction = Number((await getAccount(connection, payerAta.address)).amount);
+  const reduceCalendarPayableTx = await reduceCalendarPayable(3);
+  const refunded = Number((await getAccount(connection, payerAta.address)).amount) - payerBalanceBeforeReduction;
+  const reducedPayable = await program.account.payable.fetch(calendarPayablePDA);
+  if (refunded !== 30 || !reducedPayable.numberOfRecurrentPayment.eqn(2) || !("accepted" in reducedPayable.status)) {
+    throw new Error(`expected a refund of 30 and 2 payments left, got ${refunded}`);
+  }
+  console.log("-----------------------CALENDAR PAYABLE REDUCTION SUCCESSFUL:", reduceCalendarPayableTx.toString());
+
+  console.log("-----------------------STARTING PULLED PAYABLE WITHDRAWAL--------------------------");
+  const pullInvoiceId = new anchor.BN(12);
+  const pullPayablePDA = payablePda(pullInvoiceId);
+  const pullTerms = (recurrent: boolean) => terms({
+    recurrent: recurrent,
+    numberOfRecurrentPayment: new anchor.BN(recurrent ? 3 : 1),
+    recurrentPaymentInterval: new anchor.BN(recurrent ? 2 : 0),
+    funding: { pull: {} },
+  });
+  const createPullPayable = (recurrent: boolean) => program.methods.createPayable(
+    pullInvoiceId,
+    pullTerms(recurrent)
+  ).accounts({
+    counter: counterPDA,
+    payable: pullPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc();
+  await expectError(createPullPayable(false), "InvalidPullFunding");
+  await createPullPayable(true);
+  const pullPayableAta = getAssociatedTokenAddressSync(token, pullPayablePDA, true);
+  await program.methods.acceptPayable(
+    await payableIdx(pullPayablePDA),
+    0,
+    true,
+    { none: {} }
+  ).accounts({
+    payable: pullPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: pullPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  // nothing is locked, the payer only lets the payable pull a single installment
+  if (Number((await getAccount(connection, pullPayableAta)).amount) !== 0) {
+    throw new Error("pulled payable must not lock tokens on acceptance");
+  }
+  await approve(connection, payer, payerAta.address, pullPayablePDA, payer, 10);
+  await sleep(4500);
+  const payeeBalanceBeforePull = Number((await getAccount(connection, payeeAta.address)).amount);
+  const pullWithdrawTx = await program.methods.withdraw().accounts({
+    payable: pullPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    payeeAta: payeeAta.address,
+    payerAta: payerAta.address,
+    payableAta: pullPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  // two installments fell due, the second one was not covered by the approval
+  const pulled = Number((await getAccount(connection, payeeAta.address)).amount) - payeeBalanceBeforePull;
+  const pullPayable = await program.account.payable.fetch(pullPayablePDA);
+  if (pulled !== 10 || !pullPayable.paymentsClaimed.eqn(1) || !pullPayable.paymentsMissed.eqn(1)) {
+    throw new Error(`expected one pulled and one missed installment, pulled ${pulled}`);
+  }
+  console.log("-----------------------PULLED PAYABLE WITHDRAWAL SUCCESSFUL:", pullWithdrawTx.toString());
+
+  console.log("-----------------------STARTING PULLED PAYABLE CANCELATION--------------------------");
+  await approve(connection, payer, payerAta.address, pullPayablePDA, payer, 10);
+  await sleep(2000);
+  const payeeBalanceBeforePulledCancel = Number((await getAccount(connection, payeeAta.address)).amount);
+  const cancelPullPayableTx = await program.methods.cancelPayable().accounts({
+    payable: pullPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payeeAta: payeeAta.address,
+    payerAta: payerAta.address,
+    payableAta: pullPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  // the last installment fell due before the payer cancelled, so it is still pulled
+  const pulledOnCancel = Number((await getAccount(connection, payeeAta.address)).amount) - payeeBalanceBeforePulledCancel;
+  if (pulledOnCancel !== 10 || await program.account.payable.fetchNullable(pullPayablePDA) !== null) {
+    throw new Error(`expected the due installment to be pulled on cancelation, pulled ${pulledOnCancel}`);
+  }
+  console.log("-----------------------PULLED PAYABLE CANCELATION SUCCESSFUL:", cancelPullPayableTx.toString());
+
+  console.log("-----------------------STARTING PAST DUE PAYABLE RECOVERY--------------------------");
+  const dunningInvoiceId = new anchor.BN(13);
+  const dunningPayablePDA = payablePda(dunningInvoiceId);
+  await program.methods.createPayable(
+    dunningInvoiceId,
+    {
+      ...pullTerms(true),
+      numberOfRecurrentPayment: new anchor.BN(2),
+      recurrentPaymentInterval: new anchor.BN(4),
+      dunning: { gracePeriod: new anchor.BN(3600), maxRetries: 3 },
+    }
+  ).accounts({
+    counter: counterPDA,
+    payable: dunningPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc()
+  const dunningPayableAta = getAssociatedTokenAddressSync(token, dunningPayablePDA, true);
+  await program.methods.acceptPayable(
+    await payableIdx(dunningPayablePDA),
+    0,
+    true,
+    { none: {} }
+  ).accounts({
+    payable: dunningPayablePDA,
+    signer: payer.publicKey,
+    payee: payee.publicKey,
+    validTokenMint: token,
+    payerAta: payerAta.address,
+    payableAta: dunningPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payerSig]).rpc()
+  await sleep(4500);
+  const withdrawDunningPayable = () => program.methods.withdraw().accounts({
+    payable: dunningPayablePDA,
+    signer: payee.publicKey,
+    payer: payer.publicKey,
+    validTokenMint: token,
+    payeeAta: payeeAta.address,
+    payerAta: payerAta.address,
+    payableAta: dunningPayableAta,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId
+  }).signers([payeeSig]).rpc();
+  // the payer has not approved this payable yet, so the first pull fails and is retried
+  await withdrawDunningPayable();
+  const pastDuePayable = await program.account.payable.fetch(dunningPayablePDA);
+  if (!("pastDue" in pastDuePayable.status) || pastDuePayable.failedAttempts !== 1 || !pastDuePayable.paymentsMissed.eqn(0)) {
+    throw new Error("expected a past due payable after a failed pull");
+  }
+  // a second failed pull has to wait out the installment interval
+  await expectError(withdrawDunningPayable(), "RetryTooEarly");
+  await approve(connection, payer, payerAta.address, dunningPayablePDA, payer, 20);
+  const recoverDunningPayableTx = await withdrawDunningPayable();
+  const recoveredPayable = await program.account.payable.fetch(dunningPayablePDA);
+  if (!("accepted" in recoveredPayable.status) || recoveredPayable.failedAttempts !== 0 || !recoveredPayable.paymentsClaimed.eqn(1)) {
+    throw new Error("expected the retried pull to recover the payable");
+  }
+  console.log("-----------------------PAST DUE PAYABLE RECOVERY SUCCESSFUL:", recoverDunningPayableTx.toString());
 };
 
+// payable terms of a one-off payable of 10 tokens, overridden field by field
+function terms(overrides: object = {}) {
+  return {
+    amount: new anchor.BN(10),
+    recurrent: false,
+    numberOfRecurrentPayment: new anchor.BN(1),
+    recurrentPaymentInterval: new anchor.BN(0),
+    billingCycle: { interval: {} },
+    escalation: { none: {} },
+    payoutMode: { installments: {} },
+    funding: { escrow: {} },
+    dunning: { gracePeriod: new anchor.BN(0), maxRetries: 0 },
+    milestones: [],
+    installments: [],
+    cancelPeriod: new anchor.BN(0),
+    cancelPenalty: { none: {} },
+    acceptancePeriod: new anchor.BN(3600),
+    ...overrides,
+  };
+}
+
+async function expectError(tx: Promise<string>, code: string) {
+  try {
+    await tx;
+  } catch (error) {
+    if (error.error?.errorCode?.code === code) {
+      return;
+    }
+    throw error;
+  }
+  throw new Error(`expected transaction to fail with ${code}`);
+}
+
 function sleep(time) {
   return new Promise(resolve => setTimeout(resolve, time));
 }